@@ -0,0 +1,301 @@
+use crate::{
+    events::TripEvent,
+    metrics::Metrics,
+    state::SharedState,
+    trip::{SpeedSource, TripStatus},
+};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{
+    net::TcpListener,
+    sync::broadcast::{self, error::RecvError},
+};
+use tokio_util::sync::CancellationToken;
+
+/// The `[api]` table of the config. The server only runs if it's there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    pub listen: SocketAddr,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            listen: (Ipv4Addr::LOCALHOST, 7394).into(),
+        }
+    }
+}
+
+/// Serves the trip state as JSON until `shutdown` is cancelled:
+///
+/// - `/status`: speed, train, next stop, ETA and delay
+/// - `/stops`: every stop of the trip
+/// - `/history`: recent samples, oldest first
+/// - `/events`: a server-sent event stream of `TripEvent`s, named after
+///   their `type`, with the JSON as data
+/// - `/metrics`: Prometheus metrics about the train and the portal
+///
+/// `/status` and `/stops` answer 503 while the portal can't be reached.
+pub async fn serve(
+    listener: TcpListener,
+    state: SharedState,
+    metrics: Arc<Metrics>,
+    events: broadcast::Sender<TripEvent>,
+    shutdown: CancellationToken,
+) -> std::io::Result<()> {
+    let app = router(ApiState {
+        trip: state,
+        metrics,
+        events,
+        shutdown: shutdown.clone(),
+    });
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await
+}
+
+#[derive(Debug, Clone)]
+struct ApiState {
+    trip: SharedState,
+    metrics: Arc<Metrics>,
+    events: broadcast::Sender<TripEvent>,
+    /// Event streams never end on their own, so they watch this too to let
+    /// the graceful shutdown finish.
+    shutdown: CancellationToken,
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/stops", get(stops))
+        .route("/history", get(history))
+        .route("/events", get(events))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+/// What `/status` answers.
+#[derive(Debug, Serialize)]
+pub struct StatusView<'a> {
+    speed_kmh: Option<f64>,
+    speed_source: SpeedSource,
+    speed_implausible: bool,
+    train: Option<String>,
+    destination: Option<&'a str>,
+    next_stop: Option<NextStopView<'a>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NextStopView<'a> {
+    name: &'a str,
+    scheduled_arrival: Option<&'a str>,
+    eta: Option<&'a str>,
+    /// Ours, as opposed to the portal's `eta`.
+    predicted_arrival: Option<DateTime<Utc>>,
+    delay_minutes: Option<i64>,
+}
+
+impl<'a> StatusView<'a> {
+    pub fn new(status: &'a TripStatus, updated_at: Option<DateTime<Utc>>) -> Self {
+        Self {
+            speed_kmh: status.speed_kmh,
+            speed_source: status.speed_source,
+            speed_implausible: status.speed_implausible,
+            train: status.train(),
+            destination: status.destination.as_deref(),
+            next_stop: status.next_stop.as_ref().map(|stop| NextStopView {
+                name: &stop.name,
+                scheduled_arrival: stop.arrival.scheduled.as_deref(),
+                eta: stop.arrival.best(),
+                predicted_arrival: status.predicted_arrival,
+                delay_minutes: stop.arrival.delay_minutes(),
+            }),
+            updated_at,
+        }
+    }
+}
+
+fn not_connected() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "error": "not connected to a train portal" })),
+    )
+        .into_response()
+}
+
+async fn status(State(state): State<ApiState>) -> Response {
+    let state = state.trip.read();
+    match state.live() {
+        Some(status) => Json(StatusView::new(status, state.updated_at)).into_response(),
+        None => not_connected(),
+    }
+}
+
+async fn stops(State(state): State<ApiState>) -> Response {
+    let state = state.trip.read();
+    match state.live() {
+        Some(status) => Json(&status.stops).into_response(),
+        None => not_connected(),
+    }
+}
+
+async fn history(State(state): State<ApiState>) -> Response {
+    Json(&state.trip.read().history).into_response()
+}
+
+async fn metrics(State(state): State<ApiState>) -> Response {
+    let body = {
+        let trip = state.trip.read();
+        let portal = trip.provider.as_ref().map(|provider| provider.name());
+        state.metrics.render(portal, trip.live())
+    };
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+async fn events(
+    State(state): State<ApiState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.events.subscribe();
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                // a slow client just misses some
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .map(|event| Event::default().event(event.kind()).json_data(&event))
+    .take_until(state.shutdown.cancelled_owned());
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        providers::oebb::Oebb,
+        trip::{Stop, Times},
+    };
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn endpoints() {
+        let state = SharedState::default();
+        let metrics = Arc::new(Metrics::default());
+        let (events, _) = broadcast::channel(16);
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(serve(
+            listener,
+            state.clone(),
+            metrics.clone(),
+            events,
+            shutdown.clone(),
+        ));
+        let get = |path: &str| reqwest::get(format!("{base}{path}"));
+
+        assert_eq!(get("/status").await.unwrap().status(), 503);
+        assert_eq!(get("/stops").await.unwrap().status(), 503);
+
+        let linz = Stop {
+            name: "Linz Hbf".to_string(),
+            arrival: Times {
+                scheduled: Some("14:05".to_string()),
+                forecast: Some("14:09".to_string()),
+            },
+            departure: Times::default(),
+            ..Default::default()
+        };
+        state.write().update(
+            TripStatus {
+                speed_kmh: Some(187.0),
+                train_type: Some("RJ".to_string()),
+                trip_number: Some("61".to_string()),
+                destination: Some("Wien Hbf".to_string()),
+                next_stop: Some(linz.clone()),
+                stops: vec![linz],
+                ..Default::default()
+            },
+            Utc::now(),
+        );
+        metrics.observe_poll("oebb", true);
+        state.write().provider = Some(Arc::new(Oebb::new(vec![])));
+
+        let status: Value = get("/status").await.unwrap().json().await.unwrap();
+        assert_eq!(status["speed_kmh"], 187.0);
+        assert_eq!(status["train"], "RJ 61");
+        assert_eq!(status["next_stop"]["eta"], "14:09");
+        assert_eq!(status["next_stop"]["delay_minutes"], 4);
+
+        let stops: Value = get("/stops").await.unwrap().json().await.unwrap();
+        assert_eq!(stops[0]["name"], "Linz Hbf");
+
+        state.write().disconnect();
+        assert_eq!(get("/status").await.unwrap().status(), 503);
+        let history: Value = get("/history").await.unwrap().json().await.unwrap();
+        assert_eq!(history.as_array().unwrap().len(), 1);
+
+        let metrics = get("/metrics").await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("portal_up{portal=\"oebb\"} 0\n"));
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn event_stream() {
+        let (events, _) = broadcast::channel(16);
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(serve(
+            listener,
+            SharedState::default(),
+            Arc::default(),
+            events.clone(),
+            shutdown.clone(),
+        ));
+
+        let mut response = reqwest::get(url).await.unwrap();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+        events
+            .send(TripEvent::Arrival {
+                time: "2024-03-01T13:02:11Z".parse().unwrap(),
+                station: Some("Linz Hbf".to_string()),
+            })
+            .unwrap();
+        let chunk = response.chunk().await.unwrap().unwrap();
+        assert_eq!(
+            std::str::from_utf8(&chunk).unwrap(),
+            "event: arrival\ndata: {\"type\":\"arrival\",\"time\":\"2024-03-01T13:02:11Z\",\"station\":\"Linz Hbf\"}\n\n"
+        );
+
+        // the open stream must not hold up the shutdown
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+        assert!(response.chunk().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    command::AppEvent,
+    state::SharedState,
+    status_line,
+    trip::{TripStatus, PUNCTUAL_WITHIN_MINUTES},
+    units::SpeedUnit,
+};
+use clap::ValueEnum;
+use serde_json::json;
+use std::{
+    io::{self, Write},
+    sync::mpsc::Receiver,
+};
+use tokio_util::sync::CancellationToken;
+
+/// What the bar on the other end of stdout understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BarProtocol {
+    /// JSON lines for a waybar custom module with `"return-type": "json"`
+    Waybar,
+    /// The i3bar protocol, also spoken by swaybar
+    I3bar,
+    /// Plain text lines, e.g. for polybar's `tail = true`
+    Plain,
+}
+
+/// The status bars of Linux desktops, as an alternative to the status item:
+/// writes a line to stdout for every poll until the poller stops or whoever
+/// reads stdout goes away, in which case it cancels `shutdown`.
+pub fn run(
+    receiver: Receiver<AppEvent>,
+    state: SharedState,
+    protocol: BarProtocol,
+    format: &str,
+    unit: SpeedUnit,
+    shutdown: CancellationToken,
+) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut write = |first: bool| -> io::Result<()> {
+        let line = {
+            let state = state.read();
+            render(protocol, state.live(), format, unit)
+        };
+        match protocol {
+            // a header, then an endless array of arrays of blocks
+            BarProtocol::I3bar if first => {
+                writeln!(stdout, "{}\n[\n[{line}]", json!({"version": 1}))?
+            }
+            BarProtocol::I3bar => writeln!(stdout, ",[{line}]")?,
+            _ => writeln!(stdout, "{line}")?,
+        }
+        stdout.flush()
+    };
+
+    let result = (|| {
+        // so the bar has something before the first poll comes back
+        write(true)?;
+        for event in receiver {
+            match event {
+                AppEvent::Updated | AppEvent::PortalUnreachable => write(false)?,
+                // only the menu sends the others
+                #[cfg(target_os = "macos")]
+                _ => {}
+            }
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        shutdown.cancel();
+    }
+    result
+}
+
+fn render(
+    protocol: BarProtocol,
+    status: Option<&TripStatus>,
+    format: &str,
+    unit: SpeedUnit,
+) -> String {
+    // an empty text hides the module in waybar and i3bar
+    let text = status
+        .map(|status| status_line::line(format, status, unit))
+        .unwrap_or_default();
+    match protocol {
+        BarProtocol::Plain => text,
+        BarProtocol::Waybar => json!({
+            "text": text,
+            "tooltip": status.map(tooltip).unwrap_or_default(),
+            "class": class(status),
+        })
+        .to_string(),
+        BarProtocol::I3bar => {
+            let mut block = json!({
+                "name": "traveltracker",
+                "instance": class(status),
+                "full_text": text,
+            });
+            if class(status) == "delayed" {
+                block["color"] = "#ff9900".into();
+            }
+            block.to_string()
+        }
+    }
+}
+
+/// For styling, e.g. `#custom-traveltracker.delayed` in waybar's CSS.
+fn class(status: Option<&TripStatus>) -> &'static str {
+    match status.map(TripStatus::delay_minutes) {
+        None => "disconnected",
+        Some(Some(minutes)) if minutes > PUNCTUAL_WITHIN_MINUTES => "delayed",
+        Some(_) => "on-time",
+    }
+}
+
+/// Every stop with its arrival, the next one marked.
+fn tooltip(status: &TripStatus) -> String {
+    let next = status.next_stop.as_ref().map(|stop| &stop.name);
+    status
+        .stops
+        .iter()
+        .map(|stop| {
+            let marker = if Some(&stop.name) == next {
+                "→ "
+            } else {
+                "   "
+            };
+            let times = stop.arrival.best().or(stop.departure.best()).unwrap_or("");
+            match stop.arrival.delay_minutes().filter(|m| *m != 0) {
+                Some(delay) => format!("{marker}{times} {} ({delay:+}')", stop.name),
+                None => format!("{marker}{times} {}", stop.name),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trip::{Stop, Times};
+    use serde_json::Value;
+
+    fn rj61(forecast: &str) -> TripStatus {
+        let stop = |name: &str, scheduled: &str, forecast: &str| Stop {
+            name: name.to_string(),
+            arrival: Times {
+                scheduled: Some(scheduled.to_string()),
+                forecast: Some(forecast.to_string()),
+            },
+            departure: Times::default(),
+            ..Default::default()
+        };
+        let stops = vec![
+            stop("Linz Hbf", "14:05", forecast),
+            stop("Wien Hbf", "15:30", "15:30"),
+        ];
+        TripStatus {
+            speed_kmh: Some(187.4),
+            train_type: Some("RJ".to_string()),
+            trip_number: Some("61".to_string()),
+            destination: Some("Wien Hbf".to_string()),
+            next_stop: Some(stops[0].clone()),
+            stops,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn waybar() {
+        let render = |status: Option<&TripStatus>| {
+            let line = render(
+                BarProtocol::Waybar,
+                status,
+                status_line::DEFAULT_FORMAT,
+                SpeedUnit::Kmh,
+            );
+            serde_json::from_str::<Value>(&line).unwrap()
+        };
+
+        let late = render(Some(&rj61("14:12")));
+        assert_eq!(late["text"], "RJ 61 → Wien Hbf · 187 km/h · +7'");
+        assert_eq!(late["class"], "delayed");
+        assert_eq!(late["tooltip"], "→ 14:12 Linz Hbf (+7')\n   15:30 Wien Hbf");
+        assert_eq!(render(Some(&rj61("14:07")))["class"], "on-time");
+        let gone = render(None);
+        assert_eq!(gone["text"], "");
+        assert_eq!(gone["class"], "disconnected");
+    }
+
+    #[test]
+    fn i3bar_blocks() {
+        let line = render(
+            BarProtocol::I3bar,
+            Some(&rj61("14:12")),
+            "{speed}",
+            SpeedUnit::Kmh,
+        );
+        let block: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(block["full_text"], "187 km/h");
+        assert_eq!(block["color"], "#ff9900");
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{bar::BarProtocol, report::ReportFormat, status_line};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Config file to use instead of the default one
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Only show the status item while a train portal is reachable
+    #[arg(long)]
+    pub hidden: bool,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    /// Start traveltracker at login (LaunchAgent on macOS, systemd user unit on Linux)
+    Install {
+        /// Use an XDG autostart entry instead of a systemd user unit
+        #[arg(long)]
+        xdg_autostart: bool,
+    },
+    /// Remove whatever `install` set up
+    Uninstall,
+    /// Export recorded journeys as an iCalendar file
+    Calendar {
+        #[command(flatten)]
+        range: DateRange,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Summarize recorded journeys, e.g. for expense reports
+    Report {
+        #[command(flatten)]
+        range: DateRange,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Csv)]
+        format: ReportFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// How punctual trains were on the recorded journeys, per train or route
+    Punctuality {
+        #[command(flatten)]
+        range: DateRange,
+        /// Per route instead of per train
+        #[arg(long)]
+        by_route: bool,
+        /// Only this train or route, e.g. "RJ 61"
+        #[arg(long)]
+        only: Option<String>,
+    },
+    /// Draw the latest recorded journey on a map, as SVG or PNG
+    Map {
+        /// Write to this file instead of stdout, as PNG if it ends in .png
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print a one-line summary of the trip, e.g. for a shell prompt. Exits
+    /// with 1 when not on a train.
+    Status {
+        /// With {train}, {destination}, {speed}, {next}, {eta}, {delay} and
+        /// {delay_minutes}
+        #[arg(short, long, default_value = status_line::DEFAULT_FORMAT)]
+        format: String,
+    },
+    /// Run without the status item, writing the trip to stdout for waybar,
+    /// i3bar/swaybar or polybar instead
+    Bar {
+        #[arg(long, value_enum, default_value_t = BarProtocol::Waybar)]
+        protocol: BarProtocol,
+        /// Like `status --format`
+        #[arg(short, long, default_value = status_line::DEFAULT_FORMAT)]
+        format: String,
+    },
+    /// Manage the Träwelling account used by "Check in"
+    Traewelling {
+        #[command(subcommand)]
+        action: TraewellingAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TraewellingAction {
+    /// Save an API token in the system keychain
+    Login,
+    /// Remove the token from the keychain
+    Logout,
+}
+
+/// Which recorded journeys to look at, by the local date they started on.
+#[derive(Debug, Args)]
+pub struct DateRange {
+    /// First day to include, e.g. 2024-03-01
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+    /// Last day to include
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        let date = time.with_timezone(&Local).date_naive();
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(target_os = "macos")]
+use crate::units::SpeedUnit;
+#[cfg(target_os = "macos")]
+use status_bar::{MenuItem, MenuItemContext};
+#[cfg(target_os = "macos")]
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::Sender,
+};
+
+/// Everything that can wake up the main event loop.
+#[derive(Debug)]
+pub enum AppEvent {
+    /// The poller fetched fresh data from the portal.
+    Updated,
+    /// The poller could not reach the portal, so we're probably not on a train.
+    PortalUnreachable,
+    /// Nothing changed, but the status item should be drawn.
+    #[cfg(target_os = "macos")]
+    Redraw,
+    /// A Träwelling check-in for `train` went through (to the destination) or
+    /// didn't (with the error).
+    #[cfg(target_os = "macos")]
+    CheckedIn {
+        train: String,
+        result: Result<String, String>,
+    },
+    #[cfg(target_os = "macos")]
+    Command(AppCommand),
+}
+
+/// Things the user can ask the app to do from the menu.
+#[cfg(target_os = "macos")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppCommand {
+    OpenDashboard,
+    CopyToClipboard(String),
+    PinStop(usize),
+    UnpinStop,
+    /// Check in on Träwelling up to the stop with this index.
+    CheckIn(usize),
+    AddToCalendar,
+    ShowMap,
+    SetUnit(SpeedUnit),
+    RefreshNow,
+    TogglePause,
+    Quit,
+}
+
+#[cfg(target_os = "macos")]
+/// A menu item that posts `command` to the app when clicked.
+pub fn command_item(
+    title: impl AsRef<str>,
+    sender: &Sender<AppEvent>,
+    command: AppCommand,
+) -> MenuItem {
+    let sender = sender.clone();
+    MenuItem::new(
+        title,
+        Some(Box::new(move || post(&sender, command.clone()))),
+        None,
+    )
+}
+
+#[cfg(target_os = "macos")]
+/// A menu item whose click is turned into a command by `to_command`, which
+/// gets to see the item's tag. Handy for lists, e.g. one item per stop.
+pub fn tagged_command_item(
+    title: impl AsRef<str>,
+    tag: isize,
+    sender: &Sender<AppEvent>,
+    to_command: fn(&MenuItemContext) -> AppCommand,
+) -> MenuItem {
+    let sender = sender.clone();
+    MenuItem::new_with_tag(
+        title,
+        tag,
+        Some(Box::new(move |context| post(&sender, to_command(&context)))),
+        None,
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn post(sender: &Sender<AppEvent>, command: AppCommand) {
+    // the receiver only goes away once the event loop is gone, at which point
+    // nobody is left to act on the click anyway
+    let _ = sender.send(AppEvent::Command(command));
+}
+
+#[cfg(target_os = "macos")]
+pub fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let mut pbcopy = Command::new("pbcopy").stdin(Stdio::piped()).spawn()?;
+    pbcopy
+        .stdin
+        .take()
+        .expect("pbcopy stdin is piped")
+        .write_all(text.as_bytes())?;
+    pbcopy.wait()?;
+    Ok(())
+}
+
+/// Opens `path` with its default app.
+#[cfg(target_os = "macos")]
+pub fn open_file(path: &Path) -> io::Result<()> {
+    let status = Command::new("open").arg(path).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("open failed: {status}")));
+    }
+    Ok(())
+}
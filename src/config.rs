@@ -0,0 +1,169 @@
+use crate::{
+    api::ApiConfig, i18n::Language, mqtt::MqttConfig, providers::generic::GenericConfig,
+    speed::Smoothing, traewelling::TraewellingConfig, units::SpeedUnit, webhooks::WebhookConfig,
+};
+use serde::Deserialize;
+use std::{error::Error, fs, io::ErrorKind, path::PathBuf};
+
+/// `~/.config/traveltracker/config.toml` (or the platform's equivalent).
+/// Every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub unit: SpeedUnit,
+    /// Smooths the speed for display, see `speed::SpeedFilter`.
+    pub speed_smoothing: Smoothing,
+    /// Language of the menu.
+    pub language: Language,
+    /// Which translation to show for multilingual portal fields like station
+    /// names, most preferred first. Defaults to `language`, then German.
+    pub field_languages: Vec<String>,
+    /// Serve the trip state on localhost, see `api::serve`.
+    pub api: Option<ApiConfig>,
+    /// Publish to an MQTT broker, see `mqtt::publish`.
+    pub mqtt: Option<MqttConfig>,
+    /// Called on trip events, see `webhooks::run`.
+    pub webhooks: Vec<WebhookConfig>,
+    /// Portals beyond the built-in ones, see `providers::generic`.
+    pub providers: Vec<GenericConfig>,
+    /// Offer checking in on Träwelling, see `traewelling::Traewelling`.
+    pub traewelling: Option<TraewellingConfig>,
+    /// Where recordings and the webhook outbox are kept. Defaults to
+    /// `~/.local/share/traveltracker` (or the platform's equivalent).
+    pub data_dir: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("traveltracker/config.toml"))
+    }
+
+    /// Loads `path`, or the default path if there is none. A missing file
+    /// just means the defaults.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let Some(path) = path.or_else(Self::default_path) else {
+            return Ok(Self::default().finish());
+        };
+        let config = match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str::<Self>(&s)
+                .map_err(|e| format!("invalid config {}: {e}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(format!("can't read config {}: {e}", path.display()).into()),
+        };
+        Ok(config.finish())
+    }
+
+    pub fn data_dir(&self) -> Result<PathBuf, Box<dyn Error>> {
+        match &self.data_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(dirs::data_dir()
+                .ok_or("no data directory")?
+                .join("traveltracker")),
+        }
+    }
+
+    /// Where the running instance keeps its status for `traveltracker status`.
+    pub fn status_cache_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        Ok(dirs::cache_dir()
+            .ok_or("no cache directory")?
+            .join("traveltracker/status.json"))
+    }
+
+    pub fn journeys_dir(&self) -> Result<PathBuf, Box<dyn Error>> {
+        Ok(self.data_dir()?.join("journeys"))
+    }
+
+    fn finish(mut self) -> Self {
+        if self.field_languages.is_empty() {
+            self.field_languages = vec![self.language.code().to_string(), "de".to_string()];
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config = toml::from_str::<Config>(
+            r#"
+            unit = "m/s"
+            speed_smoothing = "kalman"
+            language = "de"
+            field_languages = ["en", "all"]
+            "#,
+        )
+        .unwrap()
+        .finish();
+        assert_eq!(config.unit, SpeedUnit::Mps);
+        assert_eq!(config.speed_smoothing, Smoothing::Kalman);
+        assert_eq!(config.language, Language::De);
+        assert_eq!(config.field_languages, ["en", "all"]);
+
+        let config = toml::from_str::<Config>("language = \"en\"")
+            .unwrap()
+            .finish();
+        assert_eq!(config.unit, SpeedUnit::Kmh);
+        assert_eq!(config.field_languages, ["en", "de"]);
+
+        assert!(toml::from_str::<Config>("unit = \"furlongs\"").is_err());
+    }
+
+    #[test]
+    fn api() {
+        assert_eq!(toml::from_str::<Config>("").unwrap().api, None);
+        let config = toml::from_str::<Config>("[api]").unwrap();
+        assert_eq!(config.api, Some(ApiConfig::default()));
+    }
+
+    #[test]
+    fn mqtt() {
+        let config = toml::from_str::<Config>("[mqtt]\nhost = \"broker.lan\"").unwrap();
+        let mqtt = config.mqtt.unwrap();
+        assert_eq!(mqtt.host, "broker.lan");
+        assert_eq!(mqtt.port, 1883);
+    }
+
+    #[test]
+    fn webhooks() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[webhooks]]
+            url = "https://example.com/hook"
+            on = ["arrival", "delay"]
+            headers = { Authorization = "Bearer 123" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.webhooks[0].headers["Authorization"], "Bearer 123");
+        assert_eq!(config.webhooks[0].delay_threshold_minutes, 5);
+        assert!(toml::from_str::<Config>("[[webhooks]]\nurl = \"x\"\non = [\"lunch\"]").is_err());
+    }
+
+    #[test]
+    fn traewelling() {
+        let config = toml::from_str::<Config>("[traewelling]").unwrap();
+        assert_eq!(config.traewelling, Some(TraewellingConfig::default()));
+    }
+
+    #[test]
+    fn providers() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[providers]]
+            name = "railnet"
+            base_url = "http://10.0.0.1"
+            endpoints = { status = "/status" }
+            fields.speed = "/speed"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.providers[0].name, "railnet");
+        assert!(toml::from_str::<Config>(
+            "[[providers]]\nname = \"x\"\nbase_url = \"y\"\nendpoints = {}\nfields = {}"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::trip::{minutes_between, Connection, Stop, Times, TripStatus};
+
+/// Less time than this to change trains is cutting it fine.
+const TIGHT_WITHIN_MINUTES: i64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Risk {
+    Safe,
+    Tight,
+    /// It leaves before we get there, unless it waits.
+    Missed,
+}
+
+/// Whether we'll make `connection` when we arrive at `arrival`, going by the
+/// forecasts for both. `None` when either time is unknown.
+pub fn risk(arrival: &Times, connection: &Connection) -> Option<Risk> {
+    let transfer = minutes_between(arrival.best()?, connection.departure.best()?)?;
+    Some(match transfer {
+        minutes if minutes < 0 => Risk::Missed,
+        minutes if minutes < TIGHT_WITHIN_MINUTES => Risk::Tight,
+        _ => Risk::Safe,
+    })
+}
+
+/// The next station and the destination, the ones of them the portal lists
+/// connections at.
+pub fn upcoming(status: &TripStatus) -> Vec<&Stop> {
+    let next = status.next_stop.as_ref().map(|next| {
+        // some portals only list them in one of the two places
+        match status.stops.iter().find(|stop| stop.name == next.name) {
+            Some(listed) if next.connections.is_empty() => listed,
+            _ => next,
+        }
+    });
+    let destination = status
+        .stops
+        .last()
+        .filter(|last| next.is_none_or(|next| next.name != last.name));
+    next.into_iter()
+        .chain(destination)
+        .filter(|stop| !stop.connections.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(scheduled: &str, forecast: &str) -> Times {
+        Times {
+            scheduled: Some(scheduled.to_string()),
+            forecast: Some(forecast.to_string()),
+        }
+    }
+
+    fn stop(name: &str, arrival: Times, connections: Vec<Connection>) -> Stop {
+        Stop {
+            name: name.to_string(),
+            arrival,
+            connections,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn risk_from_forecasts() {
+        let rex = |departure: Times| Connection {
+            line: "REX 41".to_string(),
+            departure,
+            ..Default::default()
+        };
+        let late = times("14:05", "14:12");
+        assert_eq!(risk(&late, &rex(times("14:20", "14:20"))), Some(Risk::Safe));
+        assert_eq!(
+            risk(&late, &rex(times("14:14", "14:14"))),
+            Some(Risk::Tight)
+        );
+        assert_eq!(
+            risk(&late, &rex(times("14:10", "14:10"))),
+            Some(Risk::Missed)
+        );
+        // it's late too
+        assert_eq!(risk(&late, &rex(times("14:10", "14:16"))), Some(Risk::Safe));
+        assert_eq!(
+            risk(&times("23:58", "00:01"), &rex(times("00:02", "00:02"))),
+            Some(Risk::Tight)
+        );
+        assert_eq!(risk(&Times::default(), &rex(times("14:20", "14:20"))), None);
+    }
+
+    #[test]
+    fn next_station_and_destination() {
+        let s1 = Connection {
+            line: "S1".to_string(),
+            ..Default::default()
+        };
+        let stops = vec![
+            stop("Salzburg Hbf", Times::default(), vec![]),
+            stop("Linz Hbf", Times::default(), vec![s1.clone()]),
+            stop("St. Pölten Hbf", Times::default(), vec![]),
+            stop("Wien Hbf", Times::default(), vec![s1.clone()]),
+        ];
+        let status = |next: usize, with_connections: bool| {
+            let mut next_stop = stops[next].clone();
+            if !with_connections {
+                next_stop.connections.clear();
+            }
+            TripStatus {
+                next_stop: Some(next_stop),
+                stops: stops.clone(),
+                ..Default::default()
+            }
+        };
+        let names = |status: &TripStatus| {
+            upcoming(status)
+                .iter()
+                .map(|stop| stop.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(&status(1, true)), ["Linz Hbf", "Wien Hbf"]);
+        assert_eq!(names(&status(1, false)), ["Linz Hbf", "Wien Hbf"]);
+        assert_eq!(names(&status(2, true)), ["Wien Hbf"]);
+        assert_eq!(names(&status(3, true)), ["Wien Hbf"]);
+        assert!(upcoming(&TripStatus::default()).is_empty());
+    }
+}
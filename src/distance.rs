@@ -0,0 +1,156 @@
+use crate::{
+    trip::{local_time_near, parse_time, Stop, Times, TripStatus},
+    units::SpeedUnit,
+};
+use chrono::{DateTime, Local};
+
+/// How far it is to a stop, or how long if there's nothing to tell the
+/// distance by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Remaining {
+    Km(f64),
+    Minutes(i64),
+}
+
+impl Remaining {
+    /// E.g. `"12 km"`, `"7 mi"` or `"1:25 h"`.
+    pub fn format(self, unit: SpeedUnit) -> String {
+        match self {
+            Self::Km(km) => unit.format_distance(km),
+            Self::Minutes(minutes) if minutes < 60 => format!("{minutes} min"),
+            Self::Minutes(minutes) => format!("{}:{:02} h", minutes / 60, minutes % 60),
+        }
+    }
+}
+
+/// To the next stop: as the crow flies if we know where we and the station
+/// are, otherwise the time until the forecast arrival.
+pub fn to_next(status: &TripStatus, now: DateTime<Local>) -> Option<Remaining> {
+    let next = status.next_stop.as_ref()?;
+    match next_km(status) {
+        Some(km) => Some(Remaining::Km(km)),
+        None => minutes_until(&next.arrival, now).map(Remaining::Minutes),
+    }
+}
+
+/// To the last stop, by way of the ones in between. Along the line where the
+/// portal has kilometre positions, from station to station where it has
+/// their coordinates, and as time until the arrival otherwise.
+pub fn to_destination(status: &TripStatus, now: DateTime<Local>) -> Option<Remaining> {
+    let next = status.next_stop.as_ref()?;
+    let destination = status.stops.last()?;
+    let from = status
+        .stops
+        .iter()
+        .position(|stop| stop.name == next.name)?;
+    let km = next_km(status).zip(along(&status.stops[from..]));
+    match km {
+        Some((to_next, along)) => Some(Remaining::Km(to_next + along)),
+        None => minutes_until(&destination.arrival, now).map(Remaining::Minutes),
+    }
+}
+
+fn next_km(status: &TripStatus) -> Option<f64> {
+    let next = status.next_stop.as_ref()?.position?;
+    Some(status.position?.distance_km(&next))
+}
+
+/// From the first of `stops` to the last.
+fn along(stops: &[Stop]) -> Option<f64> {
+    let (first, last) = (stops.first()?, stops.last()?);
+    if let (Some(first), Some(last)) = (first.km, last.km) {
+        return Some((last - first).abs());
+    }
+    stops
+        .windows(2)
+        .map(|pair| Some(pair[0].position?.distance_km(&pair[1].position?)))
+        .sum()
+}
+
+fn minutes_until(times: &Times, now: DateTime<Local>) -> Option<i64> {
+    let arrival = local_time_near(parse_time(times.best()?)?, now);
+    Some((arrival - now).num_minutes().max(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trip::Position;
+    use chrono::TimeZone;
+
+    fn stop(name: &str, arrival: &str, position: Option<(f64, f64)>, km: Option<f64>) -> Stop {
+        Stop {
+            name: name.to_string(),
+            arrival: Times {
+                scheduled: Some(arrival.to_string()),
+                forecast: None,
+            },
+            position: position.map(|(latitude, longitude)| Position {
+                latitude,
+                longitude,
+            }),
+            km,
+            ..Default::default()
+        }
+    }
+
+    fn rj61(with_km: bool, with_positions: bool) -> TripStatus {
+        let km = |km: f64| Some(km).filter(|_| with_km);
+        let at = |lat: f64, lon: f64| Some((lat, lon)).filter(|_| with_positions);
+        let stops = vec![
+            stop("Salzburg Hbf", "13:08", at(47.8128, 13.0456), km(0.0)),
+            stop("Linz Hbf", "14:05", at(48.2904, 14.2913), km(124.0)),
+            stop("St. Pölten Hbf", "14:52", at(48.2080, 15.6246), km(248.0)),
+            stop("Wien Hbf", "15:30", at(48.1852, 16.3766), km(310.0)),
+        ];
+        TripStatus {
+            // 10 km west of Linz
+            position: Some(Position {
+                latitude: 48.2904,
+                longitude: 14.2913 - 10.0 / 74.0,
+            }),
+            next_stop: Some(stops[1].clone()),
+            stops,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn distances() {
+        let now = Local.with_ymd_and_hms(2024, 3, 1, 13, 55, 0).unwrap();
+        let km = |remaining: Option<Remaining>| match remaining {
+            Some(Remaining::Km(km)) => km,
+            other => panic!("{other:?}"),
+        };
+
+        let status = rj61(true, true);
+        assert!((km(to_next(&status, now)) - 10.0).abs() < 0.5);
+        // along the line from Linz, plus the way there
+        assert!((km(to_destination(&status, now)) - 196.0).abs() < 0.5);
+
+        // station to station: Linz - St. Pölten - Wien as the crow flies
+        let status = rj61(false, true);
+        let expected = 10.0
+            + status.stops[1]
+                .position
+                .unwrap()
+                .distance_km(&status.stops[2].position.unwrap())
+            + status.stops[2]
+                .position
+                .unwrap()
+                .distance_km(&status.stops[3].position.unwrap());
+        assert!((km(to_destination(&status, now)) - expected).abs() < 0.5);
+
+        let status = rj61(true, false);
+        assert_eq!(to_next(&status, now), Some(Remaining::Minutes(10)));
+        assert_eq!(to_destination(&status, now), Some(Remaining::Minutes(95)));
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(Remaining::Km(12.3).format(SpeedUnit::Kmh), "12 km");
+        assert_eq!(Remaining::Km(16.09344).format(SpeedUnit::Mph), "10 mi");
+        assert_eq!(Remaining::Minutes(9).format(SpeedUnit::Kmh), "9 min");
+        assert_eq!(Remaining::Minutes(95).format(SpeedUnit::Kmh), "1:35 h");
+    }
+}
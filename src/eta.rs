@@ -0,0 +1,384 @@
+use crate::{
+    distance::{self, Remaining},
+    events::TripEvent,
+    recorder::Journey,
+    trip::{local_time_near, parse_time, TripStatus},
+};
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io::ErrorKind, path::PathBuf};
+
+/// Predictions made closer to the arrival than this aren't scored, anyone
+/// gets those right.
+const SCORE_LEAD_MINUTES: i64 = 5;
+/// Below this (km/h) the current speed says nothing about how fast the rest
+/// of the way goes.
+const MOVING_ABOVE_KMH: f64 = 10.0;
+/// Keeps the predictions for a long stretch between stations to a few
+/// hundred.
+const KEEP_EVERY_SECONDS: i64 = 30;
+
+/// From one station to the next, by name.
+type Segment = (String, String);
+
+/// Every recorded run over a segment, added up.
+#[derive(Debug, Default, Clone, Copy)]
+struct Profile {
+    km: f64,
+    hours: f64,
+}
+
+impl Profile {
+    fn speed_kmh(&self) -> Option<f64> {
+        (self.hours > 0.0).then(|| self.km / self.hours)
+    }
+}
+
+/// How far off our predictions and the portal's forecasts were, made at
+/// least `SCORE_LEAD_MINUTES` before the train arrived.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Accuracy {
+    pub arrivals: u64,
+    /// Added-up absolute errors, in minutes.
+    ours_minutes: f64,
+    portal_minutes: f64,
+    /// Arrivals the portal had a forecast for.
+    portal_arrivals: u64,
+}
+
+// Shown in the menu only.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+impl Accuracy {
+    /// Mean absolute error in minutes.
+    pub fn ours(&self) -> Option<f64> {
+        (self.arrivals > 0).then(|| self.ours_minutes / self.arrivals as f64)
+    }
+
+    pub fn portal(&self) -> Option<f64> {
+        (self.portal_arrivals > 0).then(|| self.portal_minutes / self.portal_arrivals as f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Prediction {
+    made_at: DateTime<Utc>,
+    ours: DateTime<Utc>,
+    portal: Option<DateTime<Utc>>,
+}
+
+/// Predicts the arrival at the next station from the distance left and how
+/// fast the train goes, now and on earlier recorded journeys over the same
+/// stretch, and keeps score against the portal's forecast.
+#[derive(Debug)]
+pub struct EtaEstimator {
+    profiles: HashMap<Segment, Profile>,
+    /// The station `predictions` are for.
+    station: Option<String>,
+    predictions: Vec<Prediction>,
+    accuracy: Accuracy,
+    accuracy_path: PathBuf,
+}
+
+impl EtaEstimator {
+    /// Learns from `journeys` and picks up the score kept in `accuracy_path`.
+    pub fn new(journeys: &[Journey], accuracy_path: PathBuf) -> Self {
+        let accuracy = match fs::read(&accuracy_path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                eprintln!("starting over with {}: {e}", accuracy_path.display());
+                Accuracy::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Accuracy::default(),
+            Err(e) => {
+                eprintln!("can't read {}: {e}", accuracy_path.display());
+                Accuracy::default()
+            }
+        };
+        Self {
+            profiles: profiles(journeys),
+            station: None,
+            predictions: vec![],
+            accuracy,
+            accuracy_path,
+        }
+    }
+
+    pub fn accuracy(&self) -> Accuracy {
+        self.accuracy
+    }
+
+    /// Scores the predictions for a station the train just arrived at
+    /// according to `events`, then predicts the arrival at the next one.
+    pub fn observe(&mut self, status: &mut TripStatus, events: &[TripEvent], now: DateTime<Utc>) {
+        for event in events {
+            if let TripEvent::Arrival {
+                time,
+                station: Some(station),
+            } = event
+            {
+                if self.station.as_ref() == Some(station) {
+                    self.score(*time);
+                }
+            }
+        }
+
+        let next = status.next_stop.as_ref().map(|stop| stop.name.clone());
+        if self.station != next {
+            self.station = next;
+            self.predictions.clear();
+        }
+
+        status.predicted_arrival = self.predict(status, now);
+        let due = self
+            .predictions
+            .last()
+            .is_none_or(|last| now - last.made_at >= Duration::seconds(KEEP_EVERY_SECONDS));
+        if let (Some(ours), true) = (status.predicted_arrival, due) {
+            let local_now = now.with_timezone(&Local);
+            let portal = status
+                .next_stop
+                .as_ref()
+                .and_then(|stop| parse_time(stop.arrival.best()?))
+                .map(|time| local_time_near(time, local_now).with_timezone(&Utc));
+            self.predictions.push(Prediction {
+                made_at: now,
+                ours,
+                portal,
+            });
+        }
+    }
+
+    fn predict(&self, status: &TripStatus, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let Remaining::Km(km) = distance::to_next(status, now.with_timezone(&Local))? else {
+            return None;
+        };
+        let next = status.next_stop.as_ref()?;
+        let history = status
+            .origin()
+            .and_then(|origin| {
+                let segment = (status.stops[origin].name.clone(), next.name.clone());
+                self.profiles.get(&segment)
+            })
+            .and_then(Profile::speed_kmh);
+        let current = status.speed_kmh.filter(|kmh| *kmh > MOVING_ABOVE_KMH);
+        let kmh = match (current, history) {
+            (Some(current), Some(history)) => (current + history) / 2.0,
+            (current, history) => current.or(history)?,
+        };
+        Some(now + Duration::milliseconds((km / kmh * 3_600_000.0) as i64))
+    }
+
+    fn score(&mut self, arrived_at: DateTime<Utc>) {
+        let lead = arrived_at - Duration::minutes(SCORE_LEAD_MINUTES);
+        let scored = self.predictions.iter().rev().find(|p| p.made_at <= lead);
+        if let Some(prediction) = scored {
+            let minutes_off =
+                |time: DateTime<Utc>| (time - arrived_at).num_seconds().abs() as f64 / 60.0;
+            self.accuracy.arrivals += 1;
+            self.accuracy.ours_minutes += minutes_off(prediction.ours);
+            if let Some(portal) = prediction.portal {
+                self.accuracy.portal_arrivals += 1;
+                self.accuracy.portal_minutes += minutes_off(portal);
+            }
+            self.save();
+        }
+        self.predictions.clear();
+    }
+
+    fn save(&self) {
+        let write = || -> std::io::Result<()> {
+            if let Some(parent) = self.accuracy_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let temp = self.accuracy_path.with_extension("tmp");
+            fs::write(&temp, serde_json::to_vec(&self.accuracy)?)?;
+            fs::rename(temp, &self.accuracy_path)
+        };
+        if let Err(e) = write() {
+            eprintln!("can't save {}: {e}", self.accuracy_path.display());
+        }
+    }
+}
+
+/// How fast trains went from one station to the next, from departure to
+/// arrival, on the recorded journeys. As the crow flies like
+/// `distance::to_next`, so that dividing one by the other doesn't come out
+/// early wherever the line winds.
+fn profiles(journeys: &[Journey]) -> HashMap<Segment, Profile> {
+    let mut profiles = HashMap::<Segment, Profile>::new();
+    for journey in journeys {
+        let mut departed = None;
+        for event in &journey.events {
+            match event {
+                TripEvent::Departure {
+                    time,
+                    station: Some(station),
+                } => departed = Some((station, *time)),
+                TripEvent::Arrival {
+                    time,
+                    station: Some(station),
+                } => {
+                    let Some((from, departed_at)) = departed.take() else {
+                        continue;
+                    };
+                    let mut positions = journey
+                        .samples
+                        .iter()
+                        .filter(|s| (departed_at..=*time).contains(&s.time))
+                        .filter_map(|s| Some((s.time, s.position?)));
+                    let Some((first_at, first)) = positions.next() else {
+                        continue;
+                    };
+                    let Some((last_at, last)) = positions.next_back() else {
+                        continue;
+                    };
+                    let km = first.distance_km(&last);
+                    if from == station || km <= 0.0 {
+                        continue;
+                    }
+                    let profile = profiles.entry((from.clone(), station.clone())).or_default();
+                    profile.km += km;
+                    profile.hours += (last_at - first_at).num_seconds() as f64 / 3600.0;
+                }
+                _ => {}
+            }
+        }
+    }
+    profiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::Sample,
+        trip::{Position, Stop, Times},
+    };
+
+    fn position(km_east: f64) -> Position {
+        Position {
+            latitude: 48.0,
+            longitude: 14.0 + km_east / 74.4,
+        }
+    }
+
+    /// Salzburg to Linz in an hour at 120 km/h.
+    fn recorded(start: DateTime<Utc>) -> Journey {
+        let at = |minutes: i64| start + Duration::minutes(minutes);
+        Journey {
+            train_type: None,
+            trip_number: None,
+            destination: None,
+            boarded_at: None,
+            started_at: start,
+            ended_at: at(60),
+            stops: vec![],
+            events: vec![
+                TripEvent::Departure {
+                    time: at(0),
+                    station: Some("Salzburg Hbf".to_string()),
+                },
+                TripEvent::Arrival {
+                    time: at(60),
+                    station: Some("Linz Hbf".to_string()),
+                },
+            ],
+            samples: (0..=60)
+                .map(|minute| Sample {
+                    time: at(minute),
+                    speed_kmh: Some(120.0),
+                    raw_speed_kmh: None,
+                    position: Some(position(minute as f64 * 2.0)),
+                    next_stop: None,
+                    delay_minutes: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn status(km_to_linz: f64, speed_kmh: f64, forecast: &str) -> TripStatus {
+        let stop = |name: &str, km: f64| Stop {
+            name: name.to_string(),
+            arrival: Times {
+                scheduled: Some(forecast.to_string()),
+                forecast: Some(forecast.to_string()),
+            },
+            position: Some(position(km)),
+            ..Default::default()
+        };
+        let stops = vec![stop("Salzburg Hbf", 0.0), stop("Linz Hbf", 120.0)];
+        TripStatus {
+            speed_kmh: Some(speed_kmh),
+            position: Some(position(120.0 - km_to_linz)),
+            next_stop: Some(stops[1].clone()),
+            stops,
+            ..Default::default()
+        }
+    }
+
+    /// With an accuracy file of its own for each `test`, as tests run in
+    /// parallel.
+    fn estimator(test: &str, journeys: &[Journey]) -> EtaEstimator {
+        let path = std::env::temp_dir().join(format!(
+            "traveltracker-eta-{test}-{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        EtaEstimator::new(journeys, path)
+    }
+
+    #[test]
+    fn blends_current_and_recorded_speed() {
+        let now = Utc::now();
+        let profiles = profiles(&[recorded(now - Duration::days(1))]);
+        let salzburg_linz = ("Salzburg Hbf".to_string(), "Linz Hbf".to_string());
+        assert!((profiles[&salzburg_linz].speed_kmh().unwrap() - 120.0).abs() < 0.1);
+
+        let minutes = |estimator: &mut EtaEstimator, speed_kmh: f64| {
+            let mut status = status(20.0, speed_kmh, "14:05");
+            estimator.observe(&mut status, &[], now);
+            (status.predicted_arrival.unwrap() - now).num_seconds() as f64 / 60.0
+        };
+        // 20 km at 160 km/h, or at the 140 km/h between that and 120
+        assert!((minutes(&mut estimator("blend-none", &[]), 160.0) - 7.5).abs() < 0.2);
+        let mut learned = estimator("blend-learned", &[recorded(now - Duration::days(1))]);
+        assert!((minutes(&mut learned, 160.0) - 8.6).abs() < 0.2);
+        // standing at a signal, so only the recording says anything
+        assert!((minutes(&mut learned, 0.0) - 10.0).abs() < 0.2);
+
+        let mut status = TripStatus::default();
+        learned.observe(&mut status, &[], now);
+        assert_eq!(status.predicted_arrival, None);
+    }
+
+    #[test]
+    fn keeps_score() {
+        let start = Utc::now();
+        let mut estimator = estimator("score", &[]);
+        // the portal expects us 10 minutes from now, at 120 km/h we're 5 late
+        let forecast = (start + Duration::minutes(10))
+            .with_timezone(&Local)
+            .format("%H:%M")
+            .to_string();
+        for minute in 0..15 {
+            let now = start + Duration::minutes(minute);
+            let mut status = status(30.0 - minute as f64 * 2.0, 120.0, &forecast);
+            estimator.observe(&mut status, &[], now);
+        }
+        let arrived_at = start + Duration::minutes(15);
+        let arrival = TripEvent::Arrival {
+            time: arrived_at,
+            station: Some("Linz Hbf".to_string()),
+        };
+        estimator.observe(&mut status(0.0, 0.0, &forecast), &[arrival], arrived_at);
+
+        let accuracy = estimator.accuracy();
+        assert_eq!(accuracy.arrivals, 1);
+        assert!(accuracy.ours().unwrap() < 0.1);
+        assert!((accuracy.portal().unwrap() - 5.0).abs() < 1.0);
+
+        // kept for next time
+        let reopened = EtaEstimator::new(&[], estimator.accuracy_path.clone());
+        assert_eq!(reopened.accuracy(), accuracy);
+        fs::remove_file(&estimator.accuracy_path).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+use crate::trip::TripStatus;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Below this we're standing (km/h).
+const STOPPED_BELOW: f64 = 3.0;
+/// Above this we're moving again. The gap keeps a train that is creeping
+/// into a platform from arriving and departing over and over.
+const MOVING_ABOVE: f64 = 10.0;
+
+/// Something that happened on the trip. Serialized with a `type` tag, e.g.
+///
+/// ```json
+/// {"type": "speed", "time": "2024-03-01T13:02:11Z", "speed_kmh": 187.0}
+/// {"type": "next_station", "time": "…", "previous": "Linz Hbf", "next": "St. Pölten Hbf"}
+/// {"type": "delay", "time": "…", "station": "St. Pölten Hbf", "previous_minutes": 2, "delay_minutes": 4}
+/// {"type": "arrival", "time": "…", "station": "St. Pölten Hbf"}
+/// {"type": "departure", "time": "…", "station": "St. Pölten Hbf"}
+/// {"type": "connected", "time": "…"}
+/// {"type": "disconnected", "time": "…"}
+/// ```
+///
+/// Station names and delays are `null` when the portal doesn't say.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TripEvent {
+    /// Every sample that had a speed in it.
+    Speed { time: DateTime<Utc>, speed_kmh: f64 },
+    NextStation {
+        time: DateTime<Utc>,
+        previous: Option<String>,
+        next: Option<String>,
+    },
+    /// The forecast delay at the next station changed.
+    Delay {
+        time: DateTime<Utc>,
+        station: Option<String>,
+        previous_minutes: Option<i64>,
+        delay_minutes: Option<i64>,
+    },
+    /// The train came to a halt.
+    Arrival {
+        time: DateTime<Utc>,
+        station: Option<String>,
+    },
+    /// The train got going again.
+    Departure {
+        time: DateTime<Utc>,
+        station: Option<String>,
+    },
+    /// The portal answered, for the first time or after having been gone.
+    Connected { time: DateTime<Utc> },
+    /// The portal stopped answering.
+    Disconnected { time: DateTime<Utc> },
+}
+
+impl TripEvent {
+    /// The `type` tag, also used as the SSE event name.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Speed { .. } => "speed",
+            Self::NextStation { .. } => "next_station",
+            Self::Delay { .. } => "delay",
+            Self::Arrival { .. } => "arrival",
+            Self::Departure { .. } => "departure",
+            Self::Connected { .. } => "connected",
+            Self::Disconnected { .. } => "disconnected",
+        }
+    }
+}
+
+/// Turns a series of statuses into events by comparing each one to the last.
+#[derive(Debug, Default)]
+pub struct EventDetector {
+    previous: Option<TripStatus>,
+    /// Where we stopped, while we're stopped.
+    stopped_at: Option<Option<String>>,
+    connected: bool,
+}
+
+impl EventDetector {
+    pub fn observe(&mut self, status: &TripStatus, time: DateTime<Utc>) -> Vec<TripEvent> {
+        let mut events = vec![];
+        if !self.connected {
+            events.push(TripEvent::Connected { time });
+            self.connected = true;
+        }
+
+        let next_name = status.next_stop.as_ref().map(|s| s.name.clone());
+
+        if let Some(speed_kmh) = status.speed_kmh {
+            events.push(TripEvent::Speed { time, speed_kmh });
+
+            match &self.stopped_at {
+                None if speed_kmh < STOPPED_BELOW => {
+                    events.push(TripEvent::Arrival {
+                        time,
+                        station: next_name.clone(),
+                    });
+                    self.stopped_at = Some(next_name.clone());
+                }
+                Some(station) if speed_kmh > MOVING_ABOVE => {
+                    events.push(TripEvent::Departure {
+                        time,
+                        station: station.clone(),
+                    });
+                    self.stopped_at = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(previous) = &self.previous {
+            let previous_name = previous.next_stop.as_ref().map(|s| s.name.clone());
+            if previous_name != next_name {
+                events.push(TripEvent::NextStation {
+                    time,
+                    previous: previous_name,
+                    next: next_name.clone(),
+                });
+            } else if previous.delay_minutes() != status.delay_minutes() {
+                events.push(TripEvent::Delay {
+                    time,
+                    station: next_name,
+                    previous_minutes: previous.delay_minutes(),
+                    delay_minutes: status.delay_minutes(),
+                });
+            }
+        }
+
+        self.previous = Some(status.clone());
+        events
+    }
+
+    /// The portal couldn't be reached this time.
+    pub fn disconnect(&mut self, time: DateTime<Utc>) -> Option<TripEvent> {
+        if !self.connected {
+            return None;
+        }
+        self.connected = false;
+        Some(TripEvent::Disconnected { time })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trip::{Stop, Times};
+
+    fn status(speed_kmh: f64, next: &str, forecast: &str) -> TripStatus {
+        TripStatus {
+            speed_kmh: Some(speed_kmh),
+            next_stop: Some(Stop {
+                name: next.to_string(),
+                arrival: Times {
+                    scheduled: Some("14:05".to_string()),
+                    forecast: Some(forecast.to_string()),
+                },
+                departure: Times::default(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn detects_stops_and_changes() {
+        let mut detector = EventDetector::default();
+        let time = Utc::now();
+        let kinds = |events: Vec<TripEvent>| events.iter().map(|e| e.kind()).collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds(detector.observe(&status(120.0, "Linz Hbf", "14:05"), time)),
+            ["connected", "speed"]
+        );
+        assert_eq!(
+            kinds(detector.observe(&status(110.0, "Linz Hbf", "14:07"), time)),
+            ["speed", "delay"]
+        );
+        assert_eq!(
+            kinds(detector.observe(&status(0.0, "Linz Hbf", "14:07"), time)),
+            ["speed", "arrival"]
+        );
+        // creeping forward on the platform
+        assert_eq!(
+            kinds(detector.observe(&status(5.0, "Linz Hbf", "14:07"), time)),
+            ["speed"]
+        );
+
+        let events = detector.observe(&status(30.0, "Wels Hbf", "14:20"), time);
+        assert_eq!(
+            events[1],
+            TripEvent::Departure {
+                time,
+                station: Some("Linz Hbf".to_string()),
+            }
+        );
+        assert_eq!(kinds(events), ["speed", "departure", "next_station"]);
+
+        assert!(detector.disconnect(time).is_some());
+        assert!(detector.disconnect(time).is_none());
+        assert_eq!(
+            kinds(detector.observe(&status(30.0, "Wels Hbf", "14:20"), time)),
+            ["connected", "speed"]
+        );
+    }
+
+    #[test]
+    fn serialized_with_type_tag() {
+        let event = TripEvent::Arrival {
+            time: "2024-03-01T13:02:11Z".parse().unwrap(),
+            station: Some("Linz Hbf".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "arrival", "time": "2024-03-01T13:02:11Z", "station": "Linz Hbf"})
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::trip::{Position, SpeedSource, TripStatus, MAX_PLAUSIBLE_KMH};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+
+/// How many segment speeds the median is taken over.
+const WINDOW: usize = 5;
+/// Portals update positions less often than we poll. Same position for this
+/// long means we're actually standing.
+const STANDING_AFTER_SECONDS: i64 = 10;
+/// A GPS speed older than this is no use anymore.
+const GPS_STALE_AFTER_SECONDS: i64 = 30;
+/// A portal speed that hasn't changed in this long, while GPS disagrees with
+/// it, is stuck.
+const PORTAL_STALE_AFTER_SECONDS: i64 = 30;
+/// Portal and GPS speeds disagree if they're further apart than this
+/// (km/h), or than `TOLERANCE_RATIO` of the GPS speed if that's more.
+const TOLERANCE_KMH: f64 = 20.0;
+const TOLERANCE_RATIO: f64 = 0.25;
+
+/// Works out the speed from consecutive positions, for portals that don't
+/// report one or report one that's stuck, and to check the ones that do.
+#[derive(Debug, Default)]
+pub struct GpsSpeed {
+    last_fix: Option<(DateTime<Utc>, Position)>,
+    /// Speeds between recent fixes, newest last.
+    speeds: VecDeque<f64>,
+    last_speed_at: Option<DateTime<Utc>>,
+    /// The portal's speed, and since when it's been that.
+    reported: Option<(f64, DateTime<Utc>)>,
+}
+
+impl GpsSpeed {
+    /// Fills in the speed from GPS when the portal has none or a stuck one,
+    /// and flags a portal speed that GPS disagrees with.
+    pub fn observe(&mut self, status: &mut TripStatus, time: DateTime<Utc>) {
+        if let Some(position) = status.position {
+            self.add_fix(position, time);
+        }
+        let gps_kmh = self.speed_kmh(time);
+
+        let reported = status.speed_kmh;
+        let unchanged_since = match (reported, self.reported) {
+            (Some(kmh), Some((last, since))) if kmh == last => since,
+            _ => time,
+        };
+        self.reported = reported.map(|kmh| (kmh, unchanged_since));
+
+        let Some(gps_kmh) = gps_kmh else {
+            return;
+        };
+        match reported {
+            None => {
+                status.speed_kmh = Some(gps_kmh);
+                status.speed_source = SpeedSource::Gps;
+            }
+            Some(kmh) if disagree(kmh, gps_kmh) => {
+                status.speed_implausible = true;
+                if time - unchanged_since >= Duration::seconds(PORTAL_STALE_AFTER_SECONDS) {
+                    status.speed_kmh = Some(gps_kmh);
+                    status.speed_source = SpeedSource::Gps;
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn add_fix(&mut self, position: Position, time: DateTime<Utc>) {
+        let Some((last_time, last_position)) = self.last_fix else {
+            self.last_fix = Some((time, position));
+            return;
+        };
+        let seconds = (time - last_time).num_milliseconds() as f64 / 1000.0;
+        if seconds <= 0.0 || (position == last_position && seconds < STANDING_AFTER_SECONDS as f64)
+        {
+            // the portal hasn't got a new fix yet
+            return;
+        }
+        let kmh = last_position.distance_km(&position) / seconds * 3600.0;
+        if kmh > MAX_PLAUSIBLE_KMH {
+            // a glitch, keep the last good fix, so the next one is compared to that
+            return;
+        }
+        self.last_fix = Some((time, position));
+        if self.speeds.len() == WINDOW {
+            self.speeds.pop_front();
+        }
+        self.speeds.push_back(kmh);
+        self.last_speed_at = Some(time);
+    }
+
+    /// The median of the recent segment speeds, which one bad segment can't
+    /// throw off like it would an average.
+    fn speed_kmh(&self, now: DateTime<Utc>) -> Option<f64> {
+        let fresh = self
+            .last_speed_at
+            .is_some_and(|at| now - at < Duration::seconds(GPS_STALE_AFTER_SECONDS));
+        if !fresh || self.speeds.is_empty() {
+            return None;
+        }
+        let mut speeds = self.speeds.iter().copied().collect::<Vec<_>>();
+        speeds.sort_by(f64::total_cmp);
+        Some(speeds[speeds.len() / 2])
+    }
+}
+
+fn disagree(reported_kmh: f64, gps_kmh: f64) -> bool {
+    (reported_kmh - gps_kmh).abs() > TOLERANCE_KMH.max(gps_kmh * TOLERANCE_RATIO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Heading east along the 48th parallel, where a degree of longitude is
+    /// about 74.4 km.
+    fn at(km: f64) -> Position {
+        Position {
+            latitude: 48.0,
+            longitude: 14.0 + km / 74.4,
+        }
+    }
+
+    fn status(speed_kmh: Option<f64>, km: f64) -> TripStatus {
+        TripStatus {
+            speed_kmh,
+            position: Some(at(km)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn speed_from_positions() {
+        let start = Utc::now();
+        let mut gps = GpsSpeed::default();
+        let mut km = 0.0;
+        for second in 0..10 {
+            // 180 km/h, with one fix way off
+            let glitch = if second == 6 { 5.0 } else { 0.0 };
+            let mut status = status(None, km + glitch);
+            gps.observe(&mut status, start + Duration::seconds(second));
+            km += 0.05;
+            if second >= 2 {
+                let kmh = status.speed_kmh.unwrap();
+                assert!((kmh - 180.0).abs() < 5.0, "{kmh} at {second}s");
+                assert_eq!(status.speed_source, SpeedSource::Gps);
+            }
+        }
+
+        // nothing from GPS for a while
+        let mut status = TripStatus::default();
+        gps.observe(&mut status, start + Duration::seconds(60));
+        assert_eq!(status.speed_kmh, None);
+    }
+
+    #[test]
+    fn same_position_until_the_next_fix() {
+        let start = Utc::now();
+        let mut gps = GpsSpeed::default();
+        gps.observe(&mut status(None, 0.0), start);
+        gps.observe(&mut status(None, 0.0), start + Duration::seconds(1));
+        gps.observe(&mut status(None, 0.0), start + Duration::seconds(2));
+        let mut moving = status(None, 0.1);
+        gps.observe(&mut moving, start + Duration::seconds(3));
+        assert!((moving.speed_kmh.unwrap() - 120.0).abs() < 5.0);
+    }
+
+    #[test]
+    fn cross_check() {
+        let start = Utc::now();
+        let mut gps = GpsSpeed::default();
+        let mut last = TripStatus::default();
+        for second in 0..40 {
+            // the portal says 80 the whole time, GPS says 160
+            let mut status = status(Some(80.0), second as f64 * 160.0 / 3600.0);
+            gps.observe(&mut status, start + Duration::seconds(second));
+            if second == 10 {
+                assert!(status.speed_implausible);
+                assert_eq!(status.speed_kmh, Some(80.0));
+                assert_eq!(status.speed_source, SpeedSource::Portal);
+            }
+            last = status;
+        }
+        // stuck for longer than `PORTAL_STALE_AFTER_SECONDS`
+        assert_eq!(last.speed_source, SpeedSource::Gps);
+        assert!((last.speed_kmh.unwrap() - 160.0).abs() < 5.0);
+
+        let mut agreeing = status(Some(165.0), 41.0 * 160.0 / 3600.0);
+        gps.observe(&mut agreeing, start + Duration::seconds(41));
+        assert!(!agreeing.speed_implausible);
+        assert_eq!(agreeing.speed_kmh, Some(165.0));
+    }
+}
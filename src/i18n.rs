@@ -0,0 +1,308 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    De,
+}
+
+impl Language {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::De => "de",
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn tr(self, text: Text) -> String {
+        use Text::*;
+        match (self, text) {
+            (Self::En, WaitingForPortal) => "Waiting for the train portal…".into(),
+            (Self::De, WaitingForPortal) => "Warte auf das Zugportal…".into(),
+            (Self::En, Paused) => "Paused".into(),
+            (Self::De, Paused) => "Pausiert".into(),
+            (Self::En, Offline) => "Offline".into(),
+            (Self::De, Offline) => "Offline".into(),
+            (Self::En, OnTrain { train, destination }) => format!("On {train} to {destination}"),
+            (Self::De, OnTrain { train, destination }) => format!("Im {train} nach {destination}"),
+            (Self::En, NextStation { name, arrival }) => {
+                format!("Next station: {name} at {arrival}")
+            }
+            (Self::De, NextStation { name, arrival }) => {
+                format!("Nächster Halt: {name} um {arrival}")
+            }
+            (
+                Self::En,
+                NextStationPredicted {
+                    name,
+                    arrival,
+                    predicted,
+                },
+            ) => format!("Next station: {name} at {arrival} (we say {predicted})"),
+            (
+                Self::De,
+                NextStationPredicted {
+                    name,
+                    arrival,
+                    predicted,
+                },
+            ) => format!("Nächster Halt: {name} um {arrival} (geschätzt {predicted})"),
+            (
+                Self::En,
+                EtaAccuracy {
+                    ours,
+                    portal,
+                    arrivals,
+                },
+            ) => format!("Our ETAs were off by {ours} min, the portal's by {portal} ({arrivals} arrivals)"),
+            (
+                Self::De,
+                EtaAccuracy {
+                    ours,
+                    portal,
+                    arrivals,
+                },
+            ) => format!("Unsere Schätzung lag {ours} min daneben, das Portal {portal} ({arrivals} Ankünfte)"),
+            (Self::En, ToGo { next }) => format!("{next} to go"),
+            (Self::De, ToGo { next }) => format!("Noch {next}"),
+            (
+                Self::En,
+                ToGoAndToDestination {
+                    next,
+                    destination,
+                    name,
+                },
+            ) => format!("{next} to go, {destination} to {name}"),
+            (
+                Self::De,
+                ToGoAndToDestination {
+                    next,
+                    destination,
+                    name,
+                },
+            ) => format!("Noch {next}, {destination} bis {name}"),
+            (Self::En, Connections) => "Connections".into(),
+            (Self::De, Connections) => "Anschlüsse".into(),
+            (Self::En, ConnectionsAt { name }) => format!("At {name}:"),
+            (Self::De, ConnectionsAt { name }) => format!("In {name}:"),
+            (
+                Self::En,
+                Connection {
+                    line,
+                    destination,
+                    departure,
+                    platform,
+                },
+            ) => match platform {
+                Some(platform) => {
+                    format!("{line} to {destination} at {departure}, platform {platform}")
+                }
+                None => format!("{line} to {destination} at {departure}"),
+            },
+            (
+                Self::De,
+                Connection {
+                    line,
+                    destination,
+                    departure,
+                    platform,
+                },
+            ) => match platform {
+                Some(platform) => {
+                    format!("{line} nach {destination} um {departure}, Bahnsteig {platform}")
+                }
+                None => format!("{line} nach {destination} um {departure}"),
+            },
+            (Self::En, Tight) => "tight".into(),
+            (Self::De, Tight) => "knapp".into(),
+            (Self::En, Missed) => "likely missed".into(),
+            (Self::De, Missed) => "wohl verpasst".into(),
+            (Self::En, Punctuality) => "Punctuality".into(),
+            (Self::De, Punctuality) => "Pünktlichkeit".into(),
+            (Self::En, Journeys { journeys }) => format!("{journeys} journeys on this train"),
+            (Self::De, Journeys { journeys }) => format!("{journeys} Fahrten mit diesem Zug"),
+            (Self::En, JourneysOnTime { journeys, percent }) => {
+                format!("{journeys} journeys on this train, {percent}% on time")
+            }
+            (Self::De, JourneysOnTime { journeys, percent }) => {
+                format!("{journeys} Fahrten mit diesem Zug, {percent} % pünktlich")
+            }
+            (Self::En, StationDelay { name, mean, p90 }) => {
+                format!("{name}: {mean} min on average, 90% within {p90}")
+            }
+            (Self::De, StationDelay { name, mean, p90 }) => {
+                format!("{name}: {mean} min im Schnitt, 90 % bis {p90}")
+            }
+            (Self::En, LosesTime { from, to, minutes }) => {
+                format!("Loses {minutes} min from {from} to {to}")
+            }
+            (Self::De, LosesTime { from, to, minutes }) => {
+                format!("Verliert {minutes} min von {from} bis {to}")
+            }
+            (Self::En, Pinned { name, arrival }) => format!("Pinned: {name} at {arrival}"),
+            (Self::De, Pinned { name, arrival }) => format!("Gemerkt: {name} um {arrival}"),
+            (Self::En, PinStop) => "Pin stop".into(),
+            (Self::De, PinStop) => "Halt merken".into(),
+            (Self::En, UnpinStop) => "Unpin stop".into(),
+            (Self::De, UnpinStop) => "Halt vergessen".into(),
+            (Self::En, CopyTripInfo) => "Copy trip info".into(),
+            (Self::De, CopyTripInfo) => "Fahrtinfo kopieren".into(),
+            (Self::En, CheckIn { origin }) => format!("Check in from {origin} to"),
+            (Self::De, CheckIn { origin }) => format!("Einchecken von {origin} nach"),
+            (Self::En, CheckingIn) => "Checking in…".into(),
+            (Self::De, CheckingIn) => "Checke ein…".into(),
+            (Self::En, CheckedIn { destination }) => format!("Checked in to {destination}"),
+            (Self::De, CheckedIn { destination }) => format!("Eingecheckt nach {destination}"),
+            (Self::En, CheckInFailed { error }) => format!("Check-in failed: {error}"),
+            (Self::De, CheckInFailed { error }) => format!("Einchecken fehlgeschlagen: {error}"),
+            (Self::En, AddToCalendar) => "Add to calendar".into(),
+            (Self::De, AddToCalendar) => "Zum Kalender hinzufügen".into(),
+            (Self::En, ShowMap) => "Show map".into(),
+            (Self::De, ShowMap) => "Karte anzeigen".into(),
+            (Self::En, SpeedUnit) => "Speed unit".into(),
+            (Self::De, SpeedUnit) => "Geschwindigkeitseinheit".into(),
+            (Self::En, GoToDashboard) => "Go to dashboard".into(),
+            (Self::De, GoToDashboard) => "Zum Portal".into(),
+            (Self::En, RefreshNow) => "Refresh now".into(),
+            (Self::De, RefreshNow) => "Jetzt aktualisieren".into(),
+            (Self::En, PauseUpdates) => "Pause updates".into(),
+            (Self::De, PauseUpdates) => "Aktualisierung pausieren".into(),
+            (Self::En, ResumeUpdates) => "Resume updates".into(),
+            (Self::De, ResumeUpdates) => "Aktualisierung fortsetzen".into(),
+            (Self::En, Quit) => "Quit".into(),
+            (Self::De, Quit) => "Beenden".into(),
+        }
+    }
+}
+
+/// Every user-facing string in the app.
+#[cfg(target_os = "macos")]
+#[derive(Debug, Clone, Copy)]
+pub enum Text<'a> {
+    WaitingForPortal,
+    Paused,
+    Offline,
+    OnTrain {
+        train: &'a str,
+        destination: &'a str,
+    },
+    NextStation {
+        name: &'a str,
+        arrival: &'a str,
+    },
+    NextStationPredicted {
+        name: &'a str,
+        arrival: &'a str,
+        predicted: &'a str,
+    },
+    /// Mean absolute errors in minutes.
+    EtaAccuracy {
+        ours: &'a str,
+        portal: &'a str,
+        arrivals: u64,
+    },
+    ToGo {
+        next: &'a str,
+    },
+    ToGoAndToDestination {
+        next: &'a str,
+        destination: &'a str,
+        name: &'a str,
+    },
+    Connections,
+    ConnectionsAt {
+        name: &'a str,
+    },
+    Connection {
+        line: &'a str,
+        destination: &'a str,
+        departure: &'a str,
+        platform: Option<&'a str>,
+    },
+    Tight,
+    Missed,
+    Punctuality,
+    Journeys {
+        journeys: usize,
+    },
+    JourneysOnTime {
+        journeys: usize,
+        percent: &'a str,
+    },
+    /// `p90`: the delay 90% of the journeys stayed within.
+    StationDelay {
+        name: &'a str,
+        mean: &'a str,
+        p90: &'a str,
+    },
+    LosesTime {
+        from: &'a str,
+        to: &'a str,
+        minutes: &'a str,
+    },
+    Pinned {
+        name: &'a str,
+        arrival: &'a str,
+    },
+    PinStop,
+    UnpinStop,
+    CopyTripInfo,
+    CheckIn {
+        origin: &'a str,
+    },
+    CheckingIn,
+    CheckedIn {
+        destination: &'a str,
+    },
+    CheckInFailed {
+        error: &'a str,
+    },
+    AddToCalendar,
+    ShowMap,
+    SpeedUnit,
+    GoToDashboard,
+    RefreshNow,
+    PauseUpdates,
+    ResumeUpdates,
+    Quit,
+}
+
+/// Picks a translation out of a multilingual portal field such as
+/// `{"de": "Wien Hbf", "en": "Vienna Central Station", "all": "Wien Hbf"}`,
+/// trying `languages` in order and then whatever else is there. Plain strings
+/// are returned as is.
+pub fn localized<'a>(value: &'a Value, languages: &[String]) -> Option<&'a str> {
+    match value {
+        Value::String(s) => Some(s),
+        Value::Object(translations) => languages
+            .iter()
+            .find_map(|language| translations.get(language).and_then(Value::as_str))
+            .or_else(|| translations.values().find_map(Value::as_str)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn localized_follows_fallback_order() {
+        let name = json!({"de": "Wien Hbf", "en": "Vienna Central Station"});
+        let order = |o: &[&str]| o.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            localized(&name, &order(&["en", "de"])),
+            Some("Vienna Central Station")
+        );
+        assert_eq!(localized(&name, &order(&["fr", "de"])), Some("Wien Hbf"));
+        assert!(localized(&name, &order(&["fr"])).is_some());
+        assert_eq!(localized(&json!("Linz Hbf"), &[]), Some("Linz Hbf"));
+        assert_eq!(localized(&json!(null), &order(&["de"])), None);
+    }
+}
@@ -0,0 +1,231 @@
+#[cfg(target_os = "macos")]
+use crate::command::open_file;
+use crate::{
+    cli::DateRange,
+    config::Config,
+    recorder::{load_journeys, Journey},
+    trip::{local_time_near, parse_time, Stop, Times},
+};
+use chrono::{DateTime, Local, Utc};
+use std::{error::Error, fs, path::PathBuf};
+
+/// `traveltracker calendar`: the recorded journeys in `range`, to `output` or
+/// stdout.
+pub fn export(
+    config: &Config,
+    range: &DateRange,
+    output: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let journeys = load_journeys(&config.journeys_dir()?)?
+        .into_iter()
+        .filter(|journey| range.contains(journey.started_at))
+        .collect::<Vec<_>>();
+    let ics = calendar(&journeys, Utc::now());
+    match output {
+        Some(path) => fs::write(path, ics)?,
+        None => print!("{ics}"),
+    }
+    Ok(())
+}
+
+/// Hands the journey to whatever opens `.ics` files, which for calendar apps
+/// means offering to add it.
+#[cfg(target_os = "macos")]
+pub fn add_to_calendar(journey: &Journey) -> std::io::Result<()> {
+    let path = std::env::temp_dir().join(format!(
+        "traveltracker-{}.ics",
+        journey.started_at.format("%Y%m%dT%H%M%SZ")
+    ));
+    fs::write(&path, calendar(std::slice::from_ref(journey), Utc::now()))?;
+    open_file(&path)
+}
+
+/// An iCalendar file with one event per journey.
+pub fn calendar(journeys: &[Journey], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//traveltracker//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for journey in journeys {
+        lines.extend(event(journey, now));
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines {
+        fold(&line, &mut ics);
+    }
+    ics
+}
+
+fn event(journey: &Journey, now: DateTime<Utc>) -> Vec<String> {
+    let train = journey.train().unwrap_or_else(|| "Train".to_string());
+    let from = journey.from();
+    let to = journey.to();
+    // for a trip that's still going, the forecast is the best we have
+    let end = journey
+        .arrived_at()
+        .or_else(|| {
+            let arrival = to.and_then(|to| journey.stop(to))?.arrival.best()?;
+            let near = journey.ended_at.with_timezone(&Local);
+            Some(local_time_near(parse_time(arrival)?, near).with_timezone(&Utc))
+        })
+        .unwrap_or(journey.ended_at)
+        .max(journey.departed_at());
+
+    let route = [from, to].map(|station| station.unwrap_or("?")).join(" → ");
+    vec![
+        "BEGIN:VEVENT".to_string(),
+        format!(
+            "UID:{}-{}@traveltracker",
+            journey.started_at.format("%Y%m%dT%H%M%SZ"),
+            train.replace(' ', "")
+        ),
+        format!("DTSTAMP:{}", timestamp(now)),
+        format!("DTSTART:{}", timestamp(journey.departed_at())),
+        format!("DTEND:{}", timestamp(end)),
+        format!("SUMMARY:{}", escape(&format!("{train} {route}"))),
+        format!("LOCATION:{}", escape(&route)),
+        format!("DESCRIPTION:{}", escape(&description(journey, from, to))),
+        "TRANSP:OPAQUE".to_string(),
+        "END:VEVENT".to_string(),
+    ]
+}
+
+/// The stops we passed through, with scheduled times and delays.
+fn description(journey: &Journey, from: Option<&str>, to: Option<&str>) -> String {
+    let position = |name: Option<&str>| {
+        name.and_then(|name| journey.stops.iter().position(|stop| stop.name == name))
+    };
+    let first = position(from).unwrap_or(0);
+    let last = position(to).unwrap_or(journey.stops.len().saturating_sub(1));
+
+    journey
+        .stops
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| (first..=last).contains(index))
+        .map(|(index, stop)| {
+            let times = match (index == first, index == last) {
+                (true, _) => times("dep.", &stop.departure),
+                (_, true) => times("arr.", &stop.arrival),
+                _ => Some(
+                    [times("arr.", &stop.arrival), times("dep.", &stop.departure)]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            };
+            stop_line(stop, times)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn stop_line(stop: &Stop, times: Option<String>) -> String {
+    match times.filter(|times| !times.is_empty()) {
+        Some(times) => format!("{}: {times}", stop.name),
+        None => stop.name.clone(),
+    }
+}
+
+/// E.g. `"arr. 14:05 (+4')"`.
+fn times(label: &str, times: &Times) -> Option<String> {
+    let scheduled = times.scheduled.as_deref().or(times.forecast.as_deref())?;
+    Some(match times.delay_minutes() {
+        Some(delay) if delay != 0 => format!("{label} {scheduled} ({delay:+}')"),
+        _ => format!("{label} {scheduled}"),
+    })
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Lines may be at most 75 bytes, longer ones continue on the next line after
+/// a space.
+fn fold(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::TripEvent;
+    use chrono::TimeZone;
+
+    fn stop(name: &str, arrival: [Option<&str>; 2], departure: [Option<&str>; 2]) -> Stop {
+        let times = |[scheduled, forecast]: [Option<&str>; 2]| Times {
+            scheduled: scheduled.map(str::to_string),
+            forecast: forecast.map(str::to_string),
+        };
+        Stop {
+            name: name.to_string(),
+            arrival: times(arrival),
+            departure: times(departure),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn journey_event() {
+        let time = |h, m| Utc.with_ymd_and_hms(2024, 3, 1, h, m, 0).unwrap();
+        let journey = Journey {
+            train_type: Some("RJ".to_string()),
+            trip_number: Some("61".to_string()),
+            destination: Some("Wien Hbf".to_string()),
+            boarded_at: Some("Salzburg Hbf".to_string()),
+            started_at: time(12, 0),
+            ended_at: time(14, 40),
+            stops: vec![
+                stop("Salzburg Hbf", [None, None], [Some("13:08"), Some("13:08")]),
+                stop(
+                    "Linz Hbf",
+                    [Some("14:05"), Some("14:09")],
+                    [Some("14:07"), Some("14:10")],
+                ),
+                stop("Wien Hbf", [Some("15:30"), Some("15:33")], [None, None]),
+            ],
+            events: vec![
+                TripEvent::Departure {
+                    time: time(12, 8),
+                    station: Some("Salzburg Hbf".to_string()),
+                },
+                TripEvent::Arrival {
+                    time: time(14, 33),
+                    station: Some("Wien Hbf".to_string()),
+                },
+            ],
+            samples: vec![],
+        };
+
+        let ics = calendar(&[journey], time(15, 0));
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("DTSTART:20240301T120800Z\r\n"));
+        assert!(ics.contains("DTEND:20240301T143300Z\r\n"));
+        assert!(ics.contains("SUMMARY:RJ 61 Salzburg Hbf → Wien Hbf\r\n"));
+        let description = "DESCRIPTION:Salzburg Hbf: dep. 13:08\\nLinz Hbf: arr. 14:05 (+4')\\, dep. 14:07 (+3')\\nWien Hbf: arr. 15:30 (+3')";
+        let unfolded = ics.replace("\r\n ", "");
+        assert!(unfolded.contains(description), "{unfolded}");
+        assert!(ics.lines().all(|line| line.len() <= 75));
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const LAUNCH_AGENT_LABEL: &str = "com.malted.traveltracker";
+const UNIT_NAME: &str = "traveltracker.service";
+const DESKTOP_ENTRY_NAME: &str = "traveltracker.desktop";
+
+/// Registers the current executable to start at login, in hidden mode. On
+/// Linux there's no status item, so it just runs in the background to record
+/// journeys and feed the API, MQTT and webhooks.
+pub fn install(xdg_autostart: bool) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?;
+
+    if cfg!(target_os = "macos") {
+        let path = launch_agent_path()?;
+        write(&path, &launch_agent_plist(&exe))?;
+        run("launchctl", &["load", "-w", &path.to_string_lossy()]);
+    } else if xdg_autostart {
+        write(&autostart_path()?, &desktop_entry(&exe))?;
+    } else {
+        write(&systemd_unit_path()?, &systemd_unit(&exe))?;
+        run("systemctl", &["--user", "daemon-reload"]);
+        run("systemctl", &["--user", "enable", "--now", UNIT_NAME]);
+    }
+
+    Ok(())
+}
+
+/// Undoes `install`, whichever flavour of it was used.
+pub fn uninstall() -> Result<(), Box<dyn Error>> {
+    if cfg!(target_os = "macos") {
+        let path = launch_agent_path()?;
+        if path.exists() {
+            run("launchctl", &["unload", "-w", &path.to_string_lossy()]);
+        }
+        remove(&path)?;
+    } else {
+        let unit = systemd_unit_path()?;
+        if unit.exists() {
+            run("systemctl", &["--user", "disable", "--now", UNIT_NAME]);
+        }
+        remove(&unit)?;
+        remove(&autostart_path()?)?;
+        run("systemctl", &["--user", "daemon-reload"]);
+    }
+
+    Ok(())
+}
+
+fn launch_agent_path() -> Result<PathBuf, Box<dyn Error>> {
+    let home = dirs::home_dir().ok_or("no home directory")?;
+    Ok(home
+        .join("Library/LaunchAgents")
+        .join(format!("{LAUNCH_AGENT_LABEL}.plist")))
+}
+
+fn systemd_unit_path() -> Result<PathBuf, Box<dyn Error>> {
+    let config = dirs::config_dir().ok_or("no config directory")?;
+    Ok(config.join("systemd/user").join(UNIT_NAME))
+}
+
+fn autostart_path() -> Result<PathBuf, Box<dyn Error>> {
+    let config = dirs::config_dir().ok_or("no config directory")?;
+    Ok(config.join("autostart").join(DESKTOP_ENTRY_NAME))
+}
+
+fn launch_agent_plist(exe: &Path) -> String {
+    let exe = xml_escape(&exe.to_string_lossy());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LAUNCH_AGENT_LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{exe}</string>
+        <string>--hidden</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+    <key>LimitLoadToSessionType</key>
+    <string>Aqua</string>
+</dict>
+</plist>
+"#
+    )
+}
+
+fn systemd_unit(exe: &Path) -> String {
+    format!(
+        r#"[Unit]
+Description=traveltracker
+After=graphical-session.target
+
+[Service]
+ExecStart="{}" --hidden
+# off macOS that's bar mode, and nobody reads its output here
+StandardOutput=null
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+"#,
+        exe.display()
+    )
+}
+
+fn desktop_entry(exe: &Path) -> String {
+    format!(
+        r#"[Desktop Entry]
+Type=Application
+Name=traveltracker
+Exec="{}" --hidden
+NoDisplay=true
+X-GNOME-Autostart-enabled=true
+"#,
+        exe.display()
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<(), Box<dyn Error>> {
+    match fs::remove_file(path) {
+        Ok(()) => {
+            println!("removed {}", path.display());
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Best effort: the files are what matters, the service manager will pick
+/// them up at the next login anyway.
+fn run(program: &str, args: &[&str]) {
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("`{program} {}` exited with {status}", args.join(" ")),
+        Err(e) => eprintln!("failed to run `{program}`: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plist_runs_hidden_and_escapes_path() {
+        let plist = launch_agent_plist(Path::new("/Users/a&b/traveltracker"));
+        assert!(plist.contains("<string>/Users/a&amp;b/traveltracker</string>"));
+        assert!(plist.contains("<string>--hidden</string>"));
+        assert!(plist.contains(&format!("<string>{LAUNCH_AGENT_LABEL}</string>")));
+    }
+
+    #[test]
+    fn linux_entries_run_hidden() {
+        let exe = Path::new("/home/me/.cargo/bin/traveltracker");
+        assert!(systemd_unit(exe)
+            .contains("ExecStart=\"/home/me/.cargo/bin/traveltracker\" --hidden\n"));
+        assert!(systemd_unit(exe).contains("StandardOutput=null\n"));
+        assert!(
+            desktop_entry(exe).contains("Exec=\"/home/me/.cargo/bin/traveltracker\" --hidden\n")
+        );
+    }
+}
@@ -1,120 +1,147 @@
 #![feature(async_closure)]
 
-use parking_lot::RwLock;
-use status_bar::{ns_alert, sync_infinite_event_loop, Menu, MenuItem, StatusItem};
-use std::sync::{
-    atomic::{AtomicU8, Ordering::SeqCst},
-    Arc,
-};
+mod api;
+mod bar;
+mod cli;
+mod command;
+mod config;
+// Only the menu shows these; their tests run everywhere.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod connections;
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod distance;
+mod eta;
+mod events;
+mod gps;
+mod i18n;
+mod ics;
+mod install;
+mod map;
+#[cfg(target_os = "macos")]
+mod menu;
+mod metrics;
+mod mqtt;
+mod poller;
+mod providers;
+mod punctuality;
+mod recorder;
+mod report;
+mod services;
+mod shutdown;
+mod speed;
+mod state;
+mod status_line;
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod traewelling;
+mod trip;
+mod units;
+mod webhooks;
+
+use bar::BarProtocol;
+use clap::Parser;
+use cli::{Cli, CliCommand, TraewellingAction};
+use command::AppEvent;
+use config::Config;
+use services::Services;
+use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // TODO: check if the wifi name is OEBB
-    start_statusbar().await?;
-
-    Ok(())
-}
-
-async fn start_statusbar() -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-
-    let (sender, receiver) = std::sync::mpsc::channel::<()>();
-
-    let speed = Arc::new(RwLock::new(String::new()));
-    let speed2 = speed.clone();
-
-    let combinedInfo = Arc::new(RwLock::new(serde_json::Value::Null));
-    let combinedInfo2 = combinedInfo.clone();
-
-    tokio::spawn(async move {
-        loop {
-            *speed2.write() = client
-                .get("http://192.168.32.1/api/speed")
-                .send()
-                .await
-                .unwrap()
-                .text()
-                .await
-                .unwrap();
+    let cli = Cli::parse();
+    let bar = match cli.command {
+        Some(CliCommand::Install { xdg_autostart }) => return install::install(xdg_autostart),
+        Some(CliCommand::Uninstall) => return install::uninstall(),
+        Some(CliCommand::Calendar { range, output }) => {
+            return ics::export(&Config::load(cli.config)?, &range, output)
+        }
+        Some(CliCommand::Report {
+            range,
+            format,
+            output,
+        }) => return report::export(&Config::load(cli.config)?, &range, format, output),
+        Some(CliCommand::Punctuality {
+            range,
+            by_route,
+            only,
+        }) => {
+            return punctuality::print(
+                &Config::load(cli.config)?,
+                &range,
+                by_route,
+                only.as_deref(),
+            )
+        }
+        Some(CliCommand::Map { output }) => return map::export(&Config::load(cli.config)?, output),
+        Some(CliCommand::Status { format }) => {
+            let on_train = match Config::load(cli.config) {
+                Ok(config) => status_line::print(&config, &format).await,
+                Err(e) => Err(e),
+            };
+            match on_train {
+                Ok(true) => return Ok(()),
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(2);
+                }
+            }
+        }
+        Some(CliCommand::Traewelling { action }) => {
+            return match action {
+                TraewellingAction::Login => traewelling::login(),
+                TraewellingAction::Logout => traewelling::logout(),
+            }
+        }
+        Some(CliCommand::Bar { protocol, format }) => {
+            status_line::check(&format)?;
+            Some((protocol, format))
+        }
+        None => None,
+    };
 
-            *combinedInfo2.write() = client
-                .get("http://192.168.32.1/assets/modules/fis/combined.json")
-                .send()
-                .await
-                .unwrap()
-                .json::<serde_json::Value>()
-                .await
-                .unwrap();
+    let config = Config::load(cli.config)?;
 
-            sender.send(()).unwrap();
+    let shutdown = CancellationToken::new();
+    tokio::spawn(shutdown::cancel_on_signal(shutdown.clone()));
 
-            std::thread::sleep(std::time::Duration::from_secs(1));
+    match bar {
+        Some((protocol, format)) => start_bar(config, protocol, format, shutdown).await?,
+        #[cfg(target_os = "macos")]
+        None => menu::start_statusbar(config, cli.hidden, shutdown).await?,
+        // without a menu bar to put the status item in, the trip goes to stdout
+        #[cfg(not(target_os = "macos"))]
+        None => {
+            let format = status_line::DEFAULT_FORMAT.to_string();
+            start_bar(config, BarProtocol::Waybar, format, shutdown).await?
         }
-    });
+    }
 
-    let status_item = std::cell::RefCell::new(StatusItem::new("", Menu::new(vec![])));
+    Ok(())
+}
 
-    sync_infinite_event_loop(receiver, move |_| {
-        let ci = combinedInfo.read();
-        let next_name = ci
-            .get("nextStation")
-            .expect("get next station val")
-            .get("name")
-            .expect("get next station name")
-            .get("de")
-            .expect("de name")
-            .as_str()
-            .expect("de name as str");
-        let forecast_arrival = ci
-            .get("nextStation")
-            .expect("get next station val")
-            .get("arrival")
-            .expect("get arrival val")
-            .get("forecast")
-            .expect("get forecast val")
-            .as_str()
-            .expect("forecast val as str");
-        let train_type = ci
-            .get("trainType")
-            .expect("get train type val")
-            .as_str()
-            .expect("train type val as str");
-        let trip_number = ci
-            .get("tripNumber")
-            .expect("get trip number val")
-            .as_str()
-            .expect("trip number val as str");
-        let destination_name = ci
-            .get("destination")
-            .expect("get destination val")
-            .get("all")
-            .expect("get destination name")
-            .as_str()
-            .expect("de name as str");
+/// Like `menu::start_statusbar`, but for the status bars of Linux desktops,
+/// which read the trip from stdout. Also what runs by default where there's
+/// no macOS menu bar.
+async fn start_bar(
+    config: Config,
+    protocol: BarProtocol,
+    format: String,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (sender, receiver) = std::sync::mpsc::channel::<AppEvent>();
+    let services = Services::start(&config, sender, shutdown.clone()).await?;
 
-        status_item
-            .borrow_mut()
-            .set_title(format!("{} km/h", speed.read().to_string()));
-        status_item.borrow_mut().set_menu(Menu::new(vec![
-            MenuItem::new(
-                format!("On {train_type} {trip_number} to {destination_name}"),
-                None,
-                None,
-            ),
-            MenuItem::new(
-                format!("Next station: {next_name} at {forecast_arrival}"),
-                None,
-                None,
-            ),
-            MenuItem::new(
-                format!("Go to dashboard"),
-                Some(Box::new(|| {
-                    webbrowser::open("http://192.168.32.1").unwrap()
-                })),
-                None,
-            ),
-        ]));
+    // ends once the poller is gone and with it the sender
+    let output = tokio::task::spawn_blocking({
+        let state = services.state.clone();
+        let shutdown = shutdown.clone();
+        move || bar::run(receiver, state, protocol, &format, config.unit, shutdown)
     });
 
+    services.tasks.finish().await?;
+    if let Err(e) = output.await? {
+        // most likely the bar went away, which is how it tells us to stop
+        eprintln!("stopped writing to the bar: {e}");
+    }
     Ok(())
 }
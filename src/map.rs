@@ -0,0 +1,360 @@
+#[cfg(target_os = "macos")]
+use crate::command::open_file;
+use crate::{
+    config::Config,
+    events::TripEvent,
+    recorder::{load_journeys, Journey},
+    state::Sample,
+    trip::Position,
+    units::SpeedUnit,
+};
+use chrono::{DateTime, Utc};
+use std::{error::Error, fmt::Write, fs, path::PathBuf};
+
+const WIDTH: f64 = 1200.0;
+const HEIGHT: f64 = 800.0;
+const MARGIN: f64 = 60.0;
+/// The track goes from blue through green to red at this speed (km/h).
+const FASTEST_KMH: f64 = 300.0;
+const COLORS: [(u8, u8, u8); 3] = [(0x1f, 0x5f, 0xd6), (0x2c, 0xa0, 0x2c), (0xd6, 0x27, 0x28)];
+/// Samples further apart than this aren't joined up, the train may have
+/// gone anywhere in between.
+const MAX_GAP_SECONDS: i64 = 120;
+
+/// `traveltracker map`: the latest recorded journey, as PNG if `output`
+/// ends in `.png` and as SVG otherwise.
+pub fn export(config: &Config, output: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let journeys = load_journeys(&config.journeys_dir()?)?;
+    let journey = journeys.last().ok_or("no recorded journeys")?;
+    let svg = svg(journey, config.unit).ok_or("the portal didn't report any positions")?;
+    match output {
+        Some(path) if path.extension().is_some_and(|ext| ext == "png") => {
+            fs::write(path, png(&svg)?)?
+        }
+        Some(path) => fs::write(path, svg)?,
+        None => print!("{svg}"),
+    }
+    Ok(())
+}
+
+/// Opens the map of `journey` in whatever shows SVGs.
+#[cfg(target_os = "macos")]
+pub fn show(journey: &Journey, unit: SpeedUnit) -> Result<(), Box<dyn Error>> {
+    let svg = svg(journey, unit).ok_or("the portal didn't report any positions")?;
+    let path = std::env::temp_dir().join(format!(
+        "traveltracker-{}.svg",
+        journey.started_at.format("%Y%m%dT%H%M%SZ")
+    ));
+    fs::write(&path, svg)?;
+    Ok(open_file(&path)?)
+}
+
+/// Whether there's anything to draw.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn has_track(journey: &Journey) -> bool {
+    journey
+        .samples
+        .iter()
+        .any(|sample| sample.position.is_some())
+}
+
+/// Fits positions into the image, keeping distances east-west and
+/// north-south in proportion around the middle of the track.
+struct Projection {
+    west: f64,
+    north: f64,
+    /// Pixels per degree of latitude.
+    scale: f64,
+    /// How much shorter a degree of longitude is than one of latitude.
+    shrink: f64,
+    offset: (f64, f64),
+}
+
+impl Projection {
+    fn fit(positions: &[Position]) -> Self {
+        let lats = positions.iter().map(|p| p.latitude);
+        let lons = positions.iter().map(|p| p.longitude);
+        let (south, north) = (
+            lats.clone().fold(f64::MAX, f64::min),
+            lats.fold(f64::MIN, f64::max),
+        );
+        let (west, east) = (
+            lons.clone().fold(f64::MAX, f64::min),
+            lons.fold(f64::MIN, f64::max),
+        );
+        let shrink = ((south + north) / 2.0).to_radians().cos();
+        // a standing train still gets a map, of about a kilometre around it
+        let width = ((east - west) * shrink).max(0.01);
+        let height = (north - south).max(0.01);
+        let scale = ((WIDTH - 2.0 * MARGIN) / width).min((HEIGHT - 2.0 * MARGIN) / height);
+        Self {
+            west,
+            north,
+            scale,
+            shrink,
+            offset: (
+                (WIDTH - (east - west) * shrink * scale) / 2.0,
+                (HEIGHT - (north - south) * scale) / 2.0,
+            ),
+        }
+    }
+
+    fn point(&self, position: Position) -> (f64, f64) {
+        (
+            self.offset.0 + (position.longitude - self.west) * self.shrink * self.scale,
+            self.offset.1 + (self.north - position.latitude) * self.scale,
+        )
+    }
+}
+
+/// The track of `journey` coloured by speed, its stations and where the
+/// train is now, or was last. `None` without any positions.
+pub fn svg(journey: &Journey, unit: SpeedUnit) -> Option<String> {
+    let track = journey
+        .samples
+        .iter()
+        .filter_map(|sample| Some((sample, sample.position?)))
+        .collect::<Vec<_>>();
+    let (_, current) = *track.last()?;
+    let projection = Projection::fit(&track.iter().map(|(_, p)| *p).collect::<Vec<_>>());
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="sans-serif" font-size="14">"#
+    )
+    .unwrap();
+    svg += "<rect width=\"100%\" height=\"100%\" fill=\"#fafafa\"/>\n";
+    let title = match (journey.train(), journey.from(), journey.to()) {
+        (Some(train), Some(from), Some(to)) => format!("{train} {from} → {to}"),
+        (train, ..) => train.unwrap_or_default(),
+    };
+    writeln!(
+        svg,
+        r#"<text x="20" y="32" font-size="20">{}</text>"#,
+        escape(&title)
+    )
+    .unwrap();
+
+    svg += "<g stroke-width=\"4\" stroke-linecap=\"round\">\n";
+    for pair in track.windows(2) {
+        let ((a, from), (b, to)) = (pair[0], pair[1]);
+        if (b.time - a.time).num_seconds() > MAX_GAP_SECONDS {
+            continue;
+        }
+        let kmh = match (a.speed_kmh, b.speed_kmh) {
+            (Some(a), Some(b)) => (a + b) / 2.0,
+            (speed_a, speed_b) => speed_a.or(speed_b).unwrap_or(0.0),
+        };
+        let ((x1, y1), (x2, y2)) = (projection.point(from), projection.point(to));
+        writeln!(
+            svg,
+            r#"<line x1="{x1:.1}" y1="{y1:.1}" x2="{x2:.1}" y2="{y2:.1}" stroke="{}"/>"#,
+            color(kmh)
+        )
+        .unwrap();
+    }
+    svg += "</g>\n";
+
+    for (name, position) in stations(journey) {
+        let (x, y) = projection.point(position);
+        writeln!(
+            svg,
+            "<circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"5\" fill=\"#fff\" stroke=\"#333\" stroke-width=\"2\"/>\n\
+             <text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+            x + 9.0,
+            y + 5.0,
+            escape(&name)
+        )
+        .unwrap();
+    }
+
+    let (x, y) = projection.point(current);
+    writeln!(
+        svg,
+        "<circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"8\" fill=\"#111\" stroke=\"#fff\" stroke-width=\"3\"/>"
+    )
+    .unwrap();
+
+    legend(&mut svg, unit);
+    svg += "</svg>\n";
+    Some(svg)
+}
+
+/// Where the train stopped or left from, by name, as far as the recording
+/// had a position close enough in time.
+fn stations(journey: &Journey) -> Vec<(String, Position)> {
+    let mut stations: Vec<(String, Position)> = vec![];
+    for event in &journey.events {
+        let (TripEvent::Arrival {
+            time,
+            station: Some(name),
+        }
+        | TripEvent::Departure {
+            time,
+            station: Some(name),
+        }) = event
+        else {
+            continue;
+        };
+        if stations.iter().any(|(known, _)| known == name) {
+            continue;
+        }
+        if let Some(position) = position_at(&journey.samples, *time) {
+            stations.push((name.clone(), position));
+        }
+    }
+    stations
+}
+
+fn position_at(samples: &[Sample], time: DateTime<Utc>) -> Option<Position> {
+    samples
+        .iter()
+        .filter_map(|sample| Some(((sample.time - time).num_seconds().abs(), sample.position?)))
+        .filter(|(seconds, _)| *seconds <= MAX_GAP_SECONDS)
+        .min_by_key(|(seconds, _)| *seconds)
+        .map(|(_, position)| position)
+}
+
+fn legend(svg: &mut String, unit: SpeedUnit) {
+    let (x, y, width) = (WIDTH - 260.0, HEIGHT - 40.0, 200.0);
+    *svg += "<defs><linearGradient id=\"speed\">";
+    for (index, (r, g, b)) in COLORS.iter().enumerate() {
+        let offset = index as f64 / (COLORS.len() - 1) as f64;
+        write!(
+            svg,
+            "<stop offset=\"{offset}\" stop-color=\"#{r:02x}{g:02x}{b:02x}\"/>"
+        )
+        .unwrap();
+    }
+    *svg += "</linearGradient></defs>\n";
+    writeln!(
+        svg,
+        "<rect x=\"{x}\" y=\"{y}\" width=\"{width}\" height=\"10\" fill=\"url(#speed)\"/>\n\
+         <text x=\"{x}\" y=\"{}\">0</text>\n\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+        y - 6.0,
+        x + width,
+        y - 6.0,
+        unit.format(FASTEST_KMH)
+    )
+    .unwrap();
+}
+
+fn color(kmh: f64) -> String {
+    let t = (kmh / FASTEST_KMH).clamp(0.0, 1.0) * (COLORS.len() - 1) as f64;
+    let index = (t as usize).min(COLORS.len() - 2);
+    let (from, to) = (COLORS[index], COLORS[index + 1]);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * (t - index as f64)).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(from.0, to.0),
+        mix(from.1, to.1),
+        mix(from.2, to.2)
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders `svg` with whatever fonts the system has for the labels.
+pub fn png(svg: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut options = resvg::usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = resvg::usvg::Tree::from_str(svg, &options)?;
+    let size = tree.size().to_int_size();
+    let mut pixmap =
+        resvg::tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("the map has no size")?;
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::default(),
+        &mut pixmap.as_mut(),
+    );
+    Ok(pixmap.encode_png()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn journey() -> Journey {
+        let start = Utc::now() - Duration::minutes(10);
+        let at = |seconds: i64| start + Duration::seconds(seconds);
+        let samples = (0..10)
+            .map(|i| Sample {
+                time: at(i * 5),
+                speed_kmh: Some(i as f64 * 20.0),
+                raw_speed_kmh: None,
+                position: Some(Position {
+                    latitude: 47.81 + i as f64 * 0.001,
+                    longitude: 13.04 + i as f64 * 0.002,
+                }),
+                next_stop: None,
+                delay_minutes: None,
+            })
+            .collect();
+        Journey {
+            train_type: Some("RJ".to_string()),
+            trip_number: Some("61".to_string()),
+            destination: Some("Wien Hbf".to_string()),
+            boarded_at: Some("Salzburg Hbf".to_string()),
+            started_at: start,
+            ended_at: at(45),
+            stops: vec![],
+            events: vec![
+                TripEvent::Departure {
+                    time: at(1),
+                    station: Some("Salzburg Hbf".to_string()),
+                },
+                TripEvent::Arrival {
+                    time: at(44),
+                    station: Some("Salzburg <Süd> & Co".to_string()),
+                },
+                // long after the recording, nowhere to put it
+                TripEvent::Arrival {
+                    time: at(3600),
+                    station: Some("Linz Hbf".to_string()),
+                },
+            ],
+            samples,
+        }
+    }
+
+    #[test]
+    fn draws_the_track() {
+        let journey = journey();
+        let svg = svg(&journey, SpeedUnit::Kmh).unwrap();
+
+        assert_eq!(svg.matches("<line ").count(), 9);
+        assert!(svg.contains(">Salzburg Hbf</text>"));
+        assert!(svg.contains(">Salzburg &lt;Süd&gt; &amp; Co</text>"));
+        assert!(!svg.contains(">Linz Hbf</text>"));
+        assert!(svg.contains(">300 km/h</text>"));
+        assert!(svg.contains("r=\"8\""));
+        // stays inside the image
+        for number in svg.split(['"', ' ']).filter_map(|s| s.parse::<f64>().ok()) {
+            assert!((0.0..=WIDTH).contains(&number), "{number}");
+        }
+
+        let png = png(&svg).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        let mut nowhere = journey;
+        nowhere.samples.iter_mut().for_each(|s| s.position = None);
+        assert!(!has_track(&nowhere));
+        assert_eq!(super::svg(&nowhere, SpeedUnit::Kmh), None);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(color(0.0), "#1f5fd6");
+        assert_eq!(color(150.0), "#2ca02c");
+        assert_eq!(color(300.0), "#d62728");
+        assert_eq!(color(1000.0), "#d62728");
+    }
+}
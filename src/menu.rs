@@ -0,0 +1,497 @@
+use crate::{
+    command::{command_item, copy_to_clipboard, tagged_command_item, AppCommand, AppEvent},
+    config::Config,
+    connections::{self, Risk},
+    distance,
+    i18n::{Language, Text},
+    ics, map, providers,
+    punctuality::Punctuality,
+    services::Services,
+    traewelling::Traewelling,
+    trip::{SpeedSource, TripStatus},
+    units::SpeedUnit,
+};
+use chrono::Local;
+use status_bar::{sync_event_loop, Menu, MenuItem, StatusItem};
+use std::{
+    cell::{Cell, RefCell},
+    sync::{mpsc::Sender, Arc},
+};
+use tokio_util::sync::CancellationToken;
+
+/// A Träwelling check-in for a train, and how it went (`None` while it's under
+/// way).
+type CheckIn = (String, Option<Result<String, String>>);
+
+/// Runs until `shutdown` is cancelled, either from the menu or by a signal.
+/// Everything is then torn down in order: first the event loop (which removes
+/// the status item), then the poller, then everything the poller feeds.
+///
+/// When `hidden`, the status item only exists while the train portal is
+/// reachable.
+pub async fn start_statusbar(
+    config: Config,
+    hidden: bool,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (sender, receiver) = std::sync::mpsc::channel::<AppEvent>();
+    let Services {
+        client,
+        state,
+        control,
+        recorder,
+        punctuality,
+        tasks,
+    } = Services::start(&config, sender.clone(), shutdown.clone()).await?;
+
+    let traewelling = config
+        .traewelling
+        .clone()
+        .map(|traewelling| Arc::new(Traewelling::new(client, traewelling)));
+
+    let status_item = RefCell::new(None::<StatusItem>);
+    let language = config.language;
+    let unit = Cell::new(config.unit);
+    let pinned_stop = Cell::new(None::<usize>);
+    let check_in = RefCell::new(None::<CheckIn>);
+    let shutdown2 = shutdown.clone();
+
+    // render the controls right away so the app can be quit even if the
+    // portal never answers
+    sender.send(AppEvent::Redraw)?;
+
+    let (event_loop, loop_terminator) = sync_event_loop(receiver, move |event| {
+        match event {
+            AppEvent::Updated | AppEvent::PortalUnreachable | AppEvent::Redraw => {}
+            AppEvent::CheckedIn { train, result } => {
+                *check_in.borrow_mut() = Some((train, Some(result)));
+            }
+            AppEvent::Command(command) => match command {
+                AppCommand::OpenDashboard => {
+                    let url = match &state.read().provider {
+                        Some(provider) => provider.dashboard_url().to_string(),
+                        // before any portal answered, ÖBB's is the likeliest
+                        None => providers::oebb::DASHBOARD_URL.to_string(),
+                    };
+                    if let Err(e) = webbrowser::open(&url) {
+                        eprintln!("failed to open the dashboard: {e}");
+                    }
+                }
+                AppCommand::CopyToClipboard(text) => {
+                    if let Err(e) = copy_to_clipboard(&text) {
+                        eprintln!("failed to copy to the clipboard: {e}");
+                    }
+                }
+                AppCommand::PinStop(index) => pinned_stop.set(Some(index)),
+                AppCommand::UnpinStop => pinned_stop.set(None),
+                AppCommand::CheckIn(destination) => {
+                    let status = state.read().live().cloned();
+                    if let (Some(traewelling), Some(status)) = (&traewelling, status) {
+                        let origin = status.origin();
+                        // the stop list may have changed since the menu was drawn
+                        let known = destination < status.stops.len();
+                        if let (Some(origin), Some(train), true) = (origin, status.train(), known) {
+                            *check_in.borrow_mut() = Some((train.clone(), None));
+                            let traewelling = traewelling.clone();
+                            let sender = sender.clone();
+                            tokio::spawn(async move {
+                                let (origin, destination) =
+                                    (&status.stops[origin], &status.stops[destination]);
+                                let result = traewelling
+                                    .check_in(&status, origin, destination)
+                                    .await
+                                    .map(|_| destination.name.clone())
+                                    .map_err(|e| e.to_string());
+                                let _ = sender.send(AppEvent::CheckedIn { train, result });
+                            });
+                        }
+                    }
+                }
+                AppCommand::AddToCalendar => {
+                    if let Some(journey) = recorder.current() {
+                        if let Err(e) = ics::add_to_calendar(&journey) {
+                            eprintln!("failed to add the journey to the calendar: {e}");
+                        }
+                    }
+                }
+                AppCommand::ShowMap => {
+                    if let Some(journey) = recorder.current() {
+                        if let Err(e) = map::show(&journey, unit.get()) {
+                            eprintln!("failed to show the map: {e}");
+                        }
+                    }
+                }
+                AppCommand::SetUnit(new_unit) => unit.set(new_unit),
+                AppCommand::RefreshNow => control.refresh_now(),
+                AppCommand::TogglePause => control.set_paused(!control.is_paused()),
+                AppCommand::Quit => {
+                    shutdown2.cancel();
+                    return;
+                }
+            },
+        }
+
+        let state = state.read();
+        let mut status_item = status_item.borrow_mut();
+        if hidden && !state.connected {
+            // dropping it takes it off the bar
+            *status_item = None;
+            return;
+        }
+        let status_item = status_item.get_or_insert_with(|| StatusItem::new("", Menu::new(vec![])));
+
+        let mut items = match state.live() {
+            Some(status) => {
+                let mut items =
+                    trip_menu_items(status, language, unit.get(), pinned_stop.get(), &sender);
+                if let Some(ours) = state.eta_accuracy.ours() {
+                    let portal = state.eta_accuracy.portal();
+                    items.push(MenuItem::new(
+                        language.tr(Text::EtaAccuracy {
+                            ours: &format!("{ours:.1}"),
+                            portal: &portal.map_or("?".to_string(), |m| format!("{m:.1}")),
+                            arrivals: state.eta_accuracy.arrivals,
+                        }),
+                        None,
+                        None,
+                    ));
+                }
+                items.extend(connections_item(status, language));
+                if let Some(punctuality) = status.train().and_then(|train| punctuality.get(&train))
+                {
+                    items.push(punctuality_item(punctuality, language));
+                }
+                if traewelling.is_some() {
+                    items.extend(check_in_items(
+                        status,
+                        language,
+                        check_in.borrow().as_ref(),
+                        &sender,
+                    ));
+                }
+                if let Some(journey) = recorder.current() {
+                    items.push(command_item(
+                        language.tr(Text::AddToCalendar),
+                        &sender,
+                        AppCommand::AddToCalendar,
+                    ));
+                    if map::has_track(&journey) {
+                        items.push(command_item(
+                            language.tr(Text::ShowMap),
+                            &sender,
+                            AppCommand::ShowMap,
+                        ));
+                    }
+                }
+                items
+            }
+            None => vec![MenuItem::new(
+                language.tr(Text::WaitingForPortal),
+                None,
+                None,
+            )],
+        };
+
+        items.push(MenuItem::new(
+            language.tr(Text::SpeedUnit),
+            None,
+            Some(Menu::new(
+                SpeedUnit::ALL
+                    .iter()
+                    .enumerate()
+                    .map(|(index, option)| {
+                        let checkmark = if *option == unit.get() { "✓ " } else { "" };
+                        tagged_command_item(
+                            format!("{checkmark}{}", option.symbol()),
+                            index as isize,
+                            &sender,
+                            |context| AppCommand::SetUnit(SpeedUnit::ALL[context.tag as usize]),
+                        )
+                    })
+                    .collect(),
+            )),
+        ));
+        items.push(command_item(
+            language.tr(Text::GoToDashboard),
+            &sender,
+            AppCommand::OpenDashboard,
+        ));
+        items.push(command_item(
+            language.tr(Text::RefreshNow),
+            &sender,
+            AppCommand::RefreshNow,
+        ));
+        items.push(command_item(
+            if control.is_paused() {
+                language.tr(Text::ResumeUpdates)
+            } else {
+                language.tr(Text::PauseUpdates)
+            },
+            &sender,
+            AppCommand::TogglePause,
+        ));
+        items.push(command_item(
+            language.tr(Text::Quit),
+            &sender,
+            AppCommand::Quit,
+        ));
+
+        let title = match state.live() {
+            _ if control.is_paused() => language.tr(Text::Paused),
+            Some(status) => speed_title(status, unit.get()),
+            None => language.tr(Text::Offline),
+        };
+        status_item.set_title(title);
+        status_item.set_menu(Menu::new(items));
+    });
+    let terminator = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown.cancelled().await;
+            loop_terminator.terminate();
+        }
+    });
+
+    // only returns once `shutdown` has been cancelled
+    event_loop();
+    terminator.await?;
+    // the status item lives in the loop's callback, so this takes it off the bar
+    drop(event_loop);
+
+    tasks.finish().await?;
+
+    Ok(())
+}
+
+/// The speed, and where it's from if that's not the portal, or whether it
+/// looks wrong.
+fn speed_title(status: &TripStatus, unit: SpeedUnit) -> String {
+    match status.speed_kmh {
+        Some(kmh) if status.speed_source == SpeedSource::Gps => {
+            format!("{} (GPS)", unit.format(kmh))
+        }
+        Some(kmh) if status.speed_implausible => format!("{} (?)", unit.format(kmh)),
+        Some(kmh) => unit.format(kmh),
+        None => format!("? {}", unit.symbol()),
+    }
+}
+
+/// The trains to change to at the next station and the destination, with the
+/// ones we might not make marked.
+fn connections_item(status: &TripStatus, language: Language) -> Option<MenuItem> {
+    let stops = connections::upcoming(status);
+    if stops.is_empty() {
+        return None;
+    }
+    let mut items = vec![];
+    for stop in stops {
+        items.push(MenuItem::new(
+            language.tr(Text::ConnectionsAt { name: &stop.name }),
+            None,
+            None,
+        ));
+        for connection in &stop.connections {
+            let delay = match connection.departure.delay_minutes() {
+                Some(minutes) if minutes != 0 => format!(" ({minutes:+})"),
+                _ => String::new(),
+            };
+            let line = language.tr(Text::Connection {
+                line: &connection.line,
+                destination: connection.destination.as_deref().unwrap_or("?"),
+                departure: &format!("{}{delay}", connection.departure.best().unwrap_or("?")),
+                platform: connection.platform.as_deref(),
+            });
+            let line = match connections::risk(&stop.arrival, connection) {
+                Some(Risk::Tight) => format!("⚠ {line} – {}", language.tr(Text::Tight)),
+                Some(Risk::Missed) => format!("⚠ {line} – {}", language.tr(Text::Missed)),
+                _ => line,
+            };
+            items.push(MenuItem::new(line, None, None));
+        }
+    }
+    Some(MenuItem::new(
+        language.tr(Text::Connections),
+        None,
+        Some(Menu::new(items)),
+    ))
+}
+
+/// How this train usually does, from the recorded journeys.
+fn punctuality_item(punctuality: &Punctuality, language: Language) -> MenuItem {
+    let journeys = punctuality.journeys;
+    let summary = match punctuality.on_time_percent() {
+        Some(percent) => language.tr(Text::JourneysOnTime {
+            journeys,
+            percent: &format!("{percent:.0}"),
+        }),
+        None => language.tr(Text::Journeys { journeys }),
+    };
+    let mut items = vec![MenuItem::new(summary, None, None)];
+    items.extend(punctuality.stations.iter().map(|station| {
+        let line = language.tr(Text::StationDelay {
+            name: &station.name,
+            mean: &format!("{:+.1}", station.mean()),
+            p90: &format!("{:+}", station.percentile(90.0)),
+        });
+        MenuItem::new(line, None, None)
+    }));
+    items.extend(punctuality.worst_segments().map(|segment| {
+        let line = language.tr(Text::LosesTime {
+            from: &segment.from,
+            to: &segment.to,
+            minutes: &format!("{:.1}", segment.mean()),
+        });
+        MenuItem::new(line, None, None)
+    }));
+    MenuItem::new(language.tr(Text::Punctuality), None, Some(Menu::new(items)))
+}
+
+fn trip_menu_items(
+    status: &TripStatus,
+    language: Language,
+    unit: SpeedUnit,
+    pinned_stop: Option<usize>,
+    sender: &Sender<AppEvent>,
+) -> Vec<MenuItem> {
+    let trip_line = language.tr(Text::OnTrain {
+        train: &status.train().unwrap_or_else(|| "?".to_string()),
+        destination: status.destination.as_deref().unwrap_or("?"),
+    });
+    let next_line = status.next_stop.as_ref().map(|stop| {
+        let name = &stop.name;
+        let arrival = stop.arrival.best().unwrap_or("?");
+        match status.predicted_arrival {
+            Some(predicted) => language.tr(Text::NextStationPredicted {
+                name,
+                arrival,
+                predicted: &predicted.with_timezone(&Local).format("%H:%M").to_string(),
+            }),
+            None => language.tr(Text::NextStation { name, arrival }),
+        }
+    });
+
+    let now = Local::now();
+    let remaining_line = distance::to_next(status, now).map(|next| {
+        let next = next.format(unit);
+        let destination = status.stops.last().filter(|last| {
+            status
+                .next_stop
+                .as_ref()
+                .is_some_and(|n| n.name != last.name)
+        });
+        match destination.zip(distance::to_destination(status, now)) {
+            Some((destination, remaining)) => language.tr(Text::ToGoAndToDestination {
+                next: &next,
+                destination: &remaining.format(unit),
+                name: &destination.name,
+            }),
+            None => language.tr(Text::ToGo { next: &next }),
+        }
+    });
+
+    let mut items = vec![MenuItem::new(&trip_line, None, None)];
+    if let Some(next_line) = &next_line {
+        items.push(MenuItem::new(next_line, None, None));
+    }
+    if let Some(remaining_line) = remaining_line {
+        items.push(MenuItem::new(remaining_line, None, None));
+    }
+    match pinned_stop.and_then(|index| status.stops.get(index)) {
+        Some(stop) => {
+            items.push(MenuItem::new(
+                language.tr(Text::Pinned {
+                    name: &stop.name,
+                    arrival: stop.arrival.best().unwrap_or("?"),
+                }),
+                None,
+                None,
+            ));
+            items.push(command_item(
+                language.tr(Text::UnpinStop),
+                sender,
+                AppCommand::UnpinStop,
+            ));
+        }
+        None if !status.stops.is_empty() => items.push(MenuItem::new(
+            language.tr(Text::PinStop),
+            None,
+            Some(Menu::new(
+                status
+                    .stops
+                    .iter()
+                    .enumerate()
+                    .map(|(index, stop)| {
+                        tagged_command_item(
+                            format!("{} ({})", stop.name, stop.arrival.best().unwrap_or("?")),
+                            index as isize,
+                            sender,
+                            |context| AppCommand::PinStop(context.tag as usize),
+                        )
+                    })
+                    .collect(),
+            )),
+        )),
+        None => {}
+    }
+    let trip_info = match next_line {
+        Some(next_line) => format!("{trip_line}\n{next_line}"),
+        None => trip_line,
+    };
+    items.push(command_item(
+        language.tr(Text::CopyTripInfo),
+        sender,
+        AppCommand::CopyToClipboard(trip_info),
+    ));
+    items
+}
+
+fn check_in_items(
+    status: &TripStatus,
+    language: Language,
+    check_in: Option<&CheckIn>,
+    sender: &Sender<AppEvent>,
+) -> Vec<MenuItem> {
+    let mut items = vec![];
+    match check_in {
+        Some((train, result)) if status.train().as_ref() == Some(train) => match result {
+            None => return vec![MenuItem::new(language.tr(Text::CheckingIn), None, None)],
+            Some(Ok(destination)) => {
+                return vec![MenuItem::new(
+                    language.tr(Text::CheckedIn { destination }),
+                    None,
+                    None,
+                )]
+            }
+            // let them try again below
+            Some(Err(error)) => items.push(MenuItem::new(
+                language.tr(Text::CheckInFailed { error }),
+                None,
+                None,
+            )),
+        },
+        _ => {}
+    }
+
+    if let Some(origin) = status.origin() {
+        items.push(MenuItem::new(
+            language.tr(Text::CheckIn {
+                origin: &status.stops[origin].name,
+            }),
+            None,
+            Some(Menu::new(
+                status
+                    .stops
+                    .iter()
+                    .enumerate()
+                    .skip(origin + 1)
+                    .map(|(index, stop)| {
+                        tagged_command_item(&stop.name, index as isize, sender, |context| {
+                            AppCommand::CheckIn(context.tag as usize)
+                        })
+                    })
+                    .collect(),
+            )),
+        ));
+    }
+    items
+}
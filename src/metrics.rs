@@ -0,0 +1,234 @@
+use crate::trip::TripStatus;
+use parking_lot::Mutex;
+use std::{collections::BTreeMap, fmt::Write, time::Duration};
+
+/// Upper bounds of the request latency histogram, in seconds.
+const BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counters about how the portals behave, filled in by the poller and
+/// rendered in the Prometheus text format for `/metrics`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// By portal and endpoint name.
+    requests: BTreeMap<(String, String), Requests>,
+    /// By portal.
+    polls: BTreeMap<String, Polls>,
+}
+
+#[derive(Debug, Default)]
+struct Polls {
+    succeeded: u64,
+    failed: u64,
+}
+
+#[derive(Debug, Default)]
+struct Requests {
+    /// Not cumulative, one count per bucket, plus one for `+Inf`.
+    buckets: [u64; BUCKETS.len() + 1],
+    seconds: f64,
+    count: u64,
+    errors: u64,
+}
+
+impl Metrics {
+    pub fn observe_request(&self, portal: &str, endpoint: &str, duration: Duration, ok: bool) {
+        let mut inner = self.inner.lock();
+        let requests = inner
+            .requests
+            .entry((portal.to_string(), endpoint.to_string()))
+            .or_default();
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(BUCKETS.len());
+        requests.buckets[bucket] += 1;
+        requests.seconds += seconds;
+        requests.count += 1;
+        if !ok {
+            requests.errors += 1;
+        }
+    }
+
+    pub fn observe_poll(&self, portal: &str, ok: bool) {
+        let mut inner = self.inner.lock();
+        let polls = inner.polls.entry(portal.to_string()).or_default();
+        if ok {
+            polls.succeeded += 1;
+        } else {
+            polls.failed += 1;
+        }
+    }
+
+    /// `portal` is the one we got `status` from last, `status` the live trip
+    /// status if we're connected.
+    pub fn render(&self, portal: Option<&str>, status: Option<&TripStatus>) -> String {
+        let inner = self.inner.lock();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "train_speed_kmh",
+            "gauge",
+            "Current train speed (smoothed, GPS-derived when the portal has none).",
+        );
+        let live = portal.zip(status);
+        if let Some((portal, kmh)) = live.and_then(|(p, s)| Some((p, s.speed_kmh?))) {
+            writeln!(out, "train_speed_kmh{{portal=\"{portal}\"}} {kmh}").unwrap();
+        }
+
+        header(
+            &mut out,
+            "next_stop_delay_seconds",
+            "gauge",
+            "Forecast delay at the next stop.",
+        );
+        if let Some((portal, minutes)) = live.and_then(|(p, s)| Some((p, s.delay_minutes()?))) {
+            let seconds = minutes * 60;
+            writeln!(
+                out,
+                "next_stop_delay_seconds{{portal=\"{portal}\"}} {seconds}"
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "portal_up",
+            "gauge",
+            "Whether the last poll reached the portal.",
+        );
+        for name in inner.polls.keys() {
+            let up = u8::from(live.is_some_and(|(portal, _)| portal == name));
+            writeln!(out, "portal_up{{portal=\"{name}\"}} {up}").unwrap();
+        }
+
+        header(
+            &mut out,
+            "portal_request_duration_seconds",
+            "histogram",
+            "Time taken by requests to the portal.",
+        );
+        for ((portal, endpoint), requests) in &inner.requests {
+            let labels = format!("portal=\"{portal}\",endpoint=\"{endpoint}\"");
+            let mut cumulative = 0;
+            for (le, count) in BUCKETS.iter().zip(&requests.buckets) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "portal_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "portal_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}\n\
+                 portal_request_duration_seconds_sum{{{labels}}} {}\n\
+                 portal_request_duration_seconds_count{{{labels}}} {}",
+                requests.count, requests.seconds, requests.count
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "portal_request_errors_total",
+            "counter",
+            "Requests to the portal that failed or returned garbage.",
+        );
+        for ((portal, endpoint), requests) in &inner.requests {
+            writeln!(
+                out,
+                "portal_request_errors_total{{portal=\"{portal}\",endpoint=\"{endpoint}\"}} {}",
+                requests.errors
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "portal_polls_total",
+            "counter",
+            "Polls of the portal, by whether all requests succeeded.",
+        );
+        for (portal, polls) in &inner.polls {
+            writeln!(
+                out,
+                "portal_polls_total{{portal=\"{portal}\",result=\"success\"}} {}\n\
+                 portal_polls_total{{portal=\"{portal}\",result=\"failure\"}} {}",
+                polls.succeeded, polls.failed
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "portal_poll_success_ratio",
+            "gauge",
+            "Share of polls that succeeded since start.",
+        );
+        for (portal, polls) in &inner.polls {
+            let total = polls.succeeded + polls.failed;
+            if total > 0 {
+                let ratio = polls.succeeded as f64 / total as f64;
+                writeln!(
+                    out,
+                    "portal_poll_success_ratio{{portal=\"{portal}\"}} {ratio}"
+                )
+                .unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}").unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::default();
+        metrics.observe_request("oebb", "speed", Duration::from_millis(80), true);
+        metrics.observe_request("oebb", "speed", Duration::from_secs(30), false);
+        metrics.observe_poll("oebb", true);
+        metrics.observe_poll("oebb", true);
+        metrics.observe_poll("oebb", true);
+        metrics.observe_poll("oebb", false);
+        metrics.observe_poll("westbahn", false);
+
+        let status = TripStatus {
+            speed_kmh: Some(187.0),
+            ..Default::default()
+        };
+        let out = metrics.render(Some("oebb"), Some(&status));
+
+        for line in [
+            "train_speed_kmh{portal=\"oebb\"} 187",
+            "portal_up{portal=\"oebb\"} 1",
+            "portal_up{portal=\"westbahn\"} 0",
+            "portal_request_duration_seconds_bucket{portal=\"oebb\",endpoint=\"speed\",le=\"0.05\"} 0",
+            "portal_request_duration_seconds_bucket{portal=\"oebb\",endpoint=\"speed\",le=\"0.1\"} 1",
+            "portal_request_duration_seconds_bucket{portal=\"oebb\",endpoint=\"speed\",le=\"10\"} 1",
+            "portal_request_duration_seconds_bucket{portal=\"oebb\",endpoint=\"speed\",le=\"+Inf\"} 2",
+            "portal_request_duration_seconds_count{portal=\"oebb\",endpoint=\"speed\"} 2",
+            "portal_request_errors_total{portal=\"oebb\",endpoint=\"speed\"} 1",
+            "portal_polls_total{portal=\"oebb\",result=\"failure\"} 1",
+            "portal_poll_success_ratio{portal=\"oebb\"} 0.75",
+            "portal_poll_success_ratio{portal=\"westbahn\"} 0",
+        ] {
+            assert!(out.lines().any(|l| l == line), "{line} missing from\n{out}");
+        }
+        assert!(!out.contains("next_stop_delay_seconds{"));
+    }
+}
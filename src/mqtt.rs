@@ -0,0 +1,357 @@
+use crate::{events::TripEvent, state::SharedState, trip::TripStatus};
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Notify,
+};
+use tokio_util::sync::CancellationToken;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The `[mqtt]` table of the config. Nothing is published unless it's there.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub client_id: String,
+    /// State topics are `<topic_prefix>/<sensor>`, availability is
+    /// `<topic_prefix>/availability`.
+    pub topic_prefix: String,
+    /// Where Home Assistant looks for discovery payloads.
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1883,
+            username: None,
+            password: None,
+            client_id: "traveltracker".to_string(),
+            topic_prefix: "traveltracker".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+struct Sensor {
+    key: &'static str,
+    name: &'static str,
+    icon: &'static str,
+    unit: Option<&'static str>,
+    device_class: Option<&'static str>,
+}
+
+const SENSORS: [Sensor; 4] = [
+    Sensor {
+        key: "speed",
+        name: "Speed",
+        icon: "mdi:speedometer",
+        unit: Some("km/h"),
+        device_class: Some("speed"),
+    },
+    Sensor {
+        key: "next_station",
+        name: "Next station",
+        icon: "mdi:train",
+        unit: None,
+        device_class: None,
+    },
+    Sensor {
+        key: "eta",
+        name: "Arrival at next station",
+        icon: "mdi:clock-outline",
+        unit: None,
+        device_class: None,
+    },
+    Sensor {
+        key: "delay",
+        name: "Delay",
+        icon: "mdi:clock-alert-outline",
+        unit: Some("min"),
+        device_class: Some("duration"),
+    },
+];
+
+/// Publishes the trip state to an MQTT broker, with retained state topics,
+/// Home Assistant discovery, and an availability topic that is `online` only
+/// while the portal answers. Runs until `shutdown` is cancelled.
+pub async fn publish(
+    config: MqttConfig,
+    state: SharedState,
+    mut events: broadcast::Receiver<TripEvent>,
+    shutdown: CancellationToken,
+) {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    // covers crashes and lost connections, a clean shutdown says so itself
+    options.set_last_will(LastWill::new(
+        availability_topic(&config),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password);
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+    // the event loop does the actual networking, including reconnecting
+    let connected = Arc::new(Notify::new());
+    let driver = tokio::spawn({
+        let connected = connected.clone();
+        async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => connected.notify_one(),
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("mqtt: {e}");
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        }
+    });
+
+    // what was last sent to each topic, so unchanged values aren't resent
+    let mut published = HashMap::<String, String>::new();
+    loop {
+        let messages = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = connected.notified() => {
+                // a new session, which may follow our will having been published
+                published.clear();
+                let mut messages = discovery_messages(&config);
+                messages.extend(current_messages(&config, &state));
+                messages
+            }
+            event = events.recv() => match event {
+                Ok(TripEvent::Disconnected { .. }) => {
+                    vec![(availability_topic(&config), "offline".to_string())]
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => current_messages(&config, &state),
+                Err(RecvError::Closed) => break,
+            },
+        };
+        for (topic, payload) in messages {
+            if published.get(&topic) == Some(&payload) {
+                continue;
+            }
+            // never wait on the broker, if it's away the poller will bring news soon enough
+            if client
+                .try_publish(&topic, QoS::AtLeastOnce, true, payload.as_bytes())
+                .is_ok()
+            {
+                published.insert(topic, payload);
+            }
+        }
+    }
+
+    let _ = client.try_publish(
+        availability_topic(&config),
+        QoS::AtLeastOnce,
+        true,
+        "offline",
+    );
+    let _ = client.try_disconnect();
+    if tokio::time::timeout(DISCONNECT_TIMEOUT, driver)
+        .await
+        .is_err()
+    {
+        eprintln!("mqtt: broker didn't see us off in time");
+    }
+}
+
+fn availability_topic(config: &MqttConfig) -> String {
+    format!("{}/availability", config.topic_prefix)
+}
+
+/// Availability and state as they are now.
+fn current_messages(config: &MqttConfig, state: &SharedState) -> Vec<(String, String)> {
+    match state.read().live() {
+        Some(status) => {
+            let mut messages = vec![(availability_topic(config), "online".to_string())];
+            messages.extend(state_messages(config, status));
+            messages
+        }
+        None => vec![(availability_topic(config), "offline".to_string())],
+    }
+}
+
+fn state_messages(config: &MqttConfig, status: &TripStatus) -> Vec<(String, String)> {
+    let next_stop = status.next_stop.as_ref();
+    // Home Assistant reads "None" as unknown
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "None".to_string());
+    [
+        ("speed", status.speed_kmh.map(|kmh| format!("{kmh:.0}"))),
+        ("next_station", next_stop.map(|s| s.name.clone())),
+        (
+            "eta",
+            next_stop.and_then(|s| s.arrival.best().map(str::to_string)),
+        ),
+        ("delay", status.delay_minutes().map(|m| m.to_string())),
+    ]
+    .into_iter()
+    .map(|(key, value)| (format!("{}/{key}", config.topic_prefix), or_none(value)))
+    .collect()
+}
+
+fn discovery_messages(config: &MqttConfig) -> Vec<(String, String)> {
+    let node_id = config.client_id.replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-',
+        "_",
+    );
+    SENSORS
+        .iter()
+        .map(|sensor| {
+            let mut payload = json!({
+                "name": sensor.name,
+                "unique_id": format!("{node_id}_{}", sensor.key),
+                "state_topic": format!("{}/{}", config.topic_prefix, sensor.key),
+                "availability_topic": availability_topic(config),
+                "icon": sensor.icon,
+                "device": {
+                    "identifiers": [node_id],
+                    "name": "traveltracker",
+                },
+            });
+            if let Some(unit) = sensor.unit {
+                payload["unit_of_measurement"] = unit.into();
+                payload["state_class"] = "measurement".into();
+            }
+            if let Some(device_class) = sensor.device_class {
+                payload["device_class"] = device_class.into();
+            }
+            (
+                format!(
+                    "{}/sensor/{node_id}/{}/config",
+                    config.discovery_prefix, sensor.key
+                ),
+                payload.to_string(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trip::{Stop, Times};
+    use chrono::Utc;
+    use serde_json::Value;
+
+    fn linz() -> TripStatus {
+        TripStatus {
+            speed_kmh: Some(187.4),
+            next_stop: Some(Stop {
+                name: "Linz Hbf".to_string(),
+                arrival: Times {
+                    scheduled: Some("14:05".to_string()),
+                    forecast: Some("14:09".to_string()),
+                },
+                departure: Times::default(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn messages() {
+        let config = MqttConfig::default();
+
+        assert_eq!(
+            state_messages(&config, &linz()),
+            [
+                ("traveltracker/speed", "187"),
+                ("traveltracker/next_station", "Linz Hbf"),
+                ("traveltracker/eta", "14:09"),
+                ("traveltracker/delay", "4"),
+            ]
+            .map(|(t, p)| (t.to_string(), p.to_string()))
+        );
+        assert_eq!(state_messages(&config, &TripStatus::default())[0].1, "None");
+
+        let discovery = discovery_messages(&config);
+        assert_eq!(discovery.len(), SENSORS.len());
+        let (topic, payload) = &discovery[0];
+        assert_eq!(topic, "homeassistant/sensor/traveltracker/speed/config");
+        let payload: Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload["state_topic"], "traveltracker/speed");
+        assert_eq!(payload["availability_topic"], "traveltracker/availability");
+        assert_eq!(payload["unit_of_measurement"], "km/h");
+    }
+
+    /// Needs a broker, e.g. `mosquitto -p 1883`, then
+    /// `MQTT_TEST_BROKER=localhost:1883 cargo test -- --ignored mqtt`.
+    #[tokio::test]
+    #[ignore]
+    async fn against_local_broker() {
+        let broker = std::env::var("MQTT_TEST_BROKER").unwrap_or("localhost:1883".to_string());
+        let (host, port) = broker.rsplit_once(':').unwrap();
+        let config = MqttConfig {
+            host: host.to_string(),
+            port: port.parse().unwrap(),
+            client_id: "traveltracker-test".to_string(),
+            topic_prefix: "traveltracker-test".to_string(),
+            discovery_prefix: "traveltracker-test-discovery".to_string(),
+            ..Default::default()
+        };
+
+        let (watcher, mut watcher_loop) = AsyncClient::new(
+            MqttOptions::new("traveltracker-test-watcher", &config.host, config.port),
+            16,
+        );
+        watcher
+            .subscribe("traveltracker-test/#", QoS::AtLeastOnce)
+            .await
+            .unwrap();
+        let mut next_message = async || loop {
+            if let Event::Incoming(Packet::Publish(publish)) = watcher_loop.poll().await.unwrap() {
+                return (
+                    publish.topic,
+                    String::from_utf8(publish.payload.to_vec()).unwrap(),
+                );
+            }
+        };
+
+        let state = SharedState::default();
+        state.write().update(linz(), Utc::now());
+        let (events, receiver) = broadcast::channel(16);
+        let shutdown = CancellationToken::new();
+        let publisher = tokio::spawn(publish(config, state.clone(), receiver, shutdown.clone()));
+
+        let mut seen = HashMap::new();
+        while seen.len() < 5 {
+            let (topic, payload) = next_message().await;
+            seen.insert(topic, payload);
+        }
+        assert_eq!(seen["traveltracker-test/availability"], "online");
+        assert_eq!(seen["traveltracker-test/next_station"], "Linz Hbf");
+
+        state.write().disconnect();
+        events
+            .send(TripEvent::Disconnected { time: Utc::now() })
+            .unwrap();
+        assert_eq!(
+            next_message().await,
+            (
+                "traveltracker-test/availability".to_string(),
+                "offline".to_string()
+            )
+        );
+
+        shutdown.cancel();
+        publisher.await.unwrap();
+    }
+}
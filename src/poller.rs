@@ -0,0 +1,155 @@
+use crate::{
+    command::AppEvent,
+    eta::EtaEstimator,
+    events::{EventDetector, TripEvent},
+    gps::GpsSpeed,
+    metrics::Metrics,
+    providers::Provider,
+    speed::{Smoothing, SpeedFilter},
+    state::SharedState,
+    trip::TripStatus,
+};
+use chrono::Utc;
+use futures::future::join_all;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        mpsc::Sender,
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{broadcast, Notify};
+use tokio_util::sync::CancellationToken;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Lets the rest of the app pause the poller or make it fetch right away.
+#[derive(Debug, Default)]
+pub struct PollerControl {
+    paused: AtomicBool,
+    refresh: Notify,
+}
+
+impl PollerControl {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(SeqCst)
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, SeqCst);
+    }
+
+    /// Fetch immediately, even while paused.
+    #[cfg(target_os = "macos")]
+    pub fn refresh_now(&self) {
+        self.refresh.notify_one();
+    }
+}
+
+/// Fetches from the portal every `POLL_INTERVAL` and tells everyone else
+/// about it.
+pub struct Poller {
+    pub client: reqwest::Client,
+    pub control: Arc<PollerControl>,
+    pub state: SharedState,
+    pub metrics: Arc<Metrics>,
+    pub providers: Vec<Arc<dyn Provider>>,
+    pub smoothing: Smoothing,
+    pub eta: EtaEstimator,
+}
+
+impl Poller {
+    pub async fn run(
+        mut self,
+        sender: Sender<AppEvent>,
+        events: broadcast::Sender<TripEvent>,
+        shutdown: CancellationToken,
+    ) {
+        let mut detector = EventDetector::default();
+        let mut gps = GpsSpeed::default();
+        let mut speed = SpeedFilter::new(self.smoothing);
+        let mut current = None;
+        let mut forced = false;
+        loop {
+            if forced || !self.control.is_paused() {
+                let result = tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    result = self.poll(current.clone()) => result,
+                };
+
+                let event = match result {
+                    Some((provider, mut status)) => {
+                        let now = Utc::now();
+                        speed.check(&mut status, now);
+                        gps.observe(&mut status, now);
+                        speed.smooth(&mut status, now);
+                        let new_events = detector.observe(&status, now);
+                        self.eta.observe(&mut status, &new_events, now);
+                        // listeners may look at the state when they get an
+                        // event, so it has to be up to date by then
+                        {
+                            let mut state = self.state.write();
+                            state.update(status, now);
+                            state.provider = Some(provider.clone());
+                            state.eta_accuracy = self.eta.accuracy();
+                        }
+                        current = Some(provider);
+                        publish(&events, new_events);
+                        AppEvent::Updated
+                    }
+                    None => {
+                        // maybe we changed trains, so ask everyone next time
+                        current = None;
+                        gps = GpsSpeed::default();
+                        speed = SpeedFilter::new(self.smoothing);
+                        self.state.write().disconnect();
+                        publish(&events, detector.disconnect(Utc::now()));
+                        AppEvent::PortalUnreachable
+                    }
+                };
+
+                if sender.send(event).is_err() {
+                    // the event loop is gone
+                    return;
+                }
+            }
+
+            forced = tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = tokio::time::sleep(POLL_INTERVAL) => false,
+                _ = self.control.refresh.notified() => true,
+            };
+        }
+    }
+
+    /// Asks `current` if we know which portal we're on, otherwise all of them
+    /// at once, taking the first in `providers` that answers.
+    async fn poll(
+        &self,
+        current: Option<Arc<dyn Provider>>,
+    ) -> Option<(Arc<dyn Provider>, TripStatus)> {
+        let candidates = match current {
+            Some(provider) => vec![provider],
+            None => self.providers.clone(),
+        };
+        let results = join_all(candidates.iter().map(|provider| async {
+            let result = provider.fetch(&self.client, &self.metrics).await;
+            self.metrics.observe_poll(provider.name(), result.is_ok());
+            result
+        }))
+        .await;
+        candidates
+            .into_iter()
+            .zip(results)
+            .find_map(|(provider, result)| Some((provider, result.ok()?)))
+    }
+}
+
+fn publish(events: &broadcast::Sender<TripEvent>, new_events: impl IntoIterator<Item = TripEvent>) {
+    for event in new_events {
+        // fine if nobody is listening
+        let _ = events.send(event);
+    }
+}
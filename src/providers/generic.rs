@@ -0,0 +1,466 @@
+use super::{get_text, time_of_day, timed, FetchError, Provider};
+use crate::{
+    i18n::localized,
+    metrics::Metrics,
+    trip::{Position, Stop, Times, TripStatus},
+};
+use chrono::DateTime;
+use futures::future::{try_join_all, BoxFuture};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering::SeqCst},
+};
+
+/// A portal described in the config rather than in code:
+///
+/// ```toml
+/// [[providers]]
+/// name = "railnet"
+/// base_url = "http://192.168.32.1"
+/// probe = "/api/speed"
+/// endpoints = { speed = "/api/speed", combined = "/assets/modules/fis/combined.json" }
+/// fields.speed = "speed:$"
+/// fields.next_station = "combined:$.nextStation.name"
+/// fields.forecast_arrival = "combined:/nextStation/arrival/forecast"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawGenericConfig")]
+pub struct GenericConfig {
+    /// For metrics labels and logs.
+    pub name: String,
+    pub base_url: String,
+    /// Fetched to find out whether we're on one of this portal's trains,
+    /// before fetching anything else.
+    pub probe: Option<String>,
+    /// Paths relative to `base_url` by the names the mappings use.
+    pub endpoints: BTreeMap<String, String>,
+    pub fields: BTreeMap<Field, Mapping>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawGenericConfig {
+    name: String,
+    base_url: String,
+    probe: Option<String>,
+    endpoints: BTreeMap<String, String>,
+    fields: BTreeMap<Field, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    /// In km/h.
+    Speed,
+    Latitude,
+    Longitude,
+    TrainType,
+    TripNumber,
+    Destination,
+    NextStation,
+    ScheduledArrival,
+    ForecastArrival,
+}
+
+/// Where a field is: which endpoint, and where in its JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    pub endpoint: String,
+    /// A JSON pointer, even if the config had a JSONPath.
+    pub pointer: String,
+}
+
+impl TryFrom<RawGenericConfig> for GenericConfig {
+    type Error = String;
+
+    /// Reports every field that doesn't resolve, not just the first.
+    fn try_from(raw: RawGenericConfig) -> Result<Self, String> {
+        if raw.endpoints.is_empty() {
+            return Err(format!("provider `{}` has no endpoints", raw.name));
+        }
+        let mut fields = BTreeMap::new();
+        let mut errors = vec![];
+        for (field, mapping) in raw.fields {
+            match Mapping::parse(&mapping, &raw.endpoints) {
+                Ok(mapping) => {
+                    fields.insert(field, mapping);
+                }
+                Err(e) => errors.push(format!("{field} ({e})")),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(format!(
+                "provider `{}`: fields that don't resolve: {}",
+                raw.name,
+                errors.join(", ")
+            ));
+        }
+        if !fields.contains_key(&Field::Speed) && !fields.contains_key(&Field::NextStation) {
+            return Err(format!(
+                "provider `{}` needs at least `speed` or `next_station`",
+                raw.name
+            ));
+        }
+        Ok(Self {
+            name: raw.name,
+            base_url: raw.base_url.trim_end_matches('/').to_string(),
+            probe: raw.probe,
+            endpoints: raw.endpoints,
+            fields,
+        })
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Field::Speed => "speed",
+            Field::Latitude => "latitude",
+            Field::Longitude => "longitude",
+            Field::TrainType => "train_type",
+            Field::TripNumber => "trip_number",
+            Field::Destination => "destination",
+            Field::NextStation => "next_station",
+            Field::ScheduledArrival => "scheduled_arrival",
+            Field::ForecastArrival => "forecast_arrival",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Mapping {
+    /// `endpoint:path`, where the endpoint can be left out if there's only
+    /// one, and the path is either a JSON pointer (`/nextStation/name/de`) or
+    /// a JSONPath of the `$.nextStation.name.de` kind.
+    fn parse(mapping: &str, endpoints: &BTreeMap<String, String>) -> Result<Self, String> {
+        let (endpoint, path) = match mapping.split_once(':') {
+            Some((endpoint, path)) if !endpoint.starts_with(['/', '$']) => {
+                (endpoint.to_string(), path)
+            }
+            _ if endpoints.len() == 1 => (endpoints.keys().next().unwrap().clone(), mapping),
+            _ => return Err("which endpoint? write `endpoint:path`".to_string()),
+        };
+        if !endpoints.contains_key(&endpoint) {
+            return Err(format!("no endpoint `{endpoint}`"));
+        }
+        let pointer = match path {
+            "" => String::new(),
+            path if path.starts_with('/') => path.to_string(),
+            path if path.starts_with('$') => json_path_to_pointer(path)?,
+            path => return Err(format!("`{path}` is neither a JSON pointer nor a JSONPath")),
+        };
+        Ok(Self { endpoint, pointer })
+    }
+}
+
+/// The subset of JSONPath that points at one value: `$`, `.name`, `[0]` and
+/// `['name']`.
+fn json_path_to_pointer(path: &str) -> Result<String, String> {
+    let unsupported = || format!("`{path}`: only paths like `$.a.b[0]` are supported");
+    let escape = |key: &str| key.replace('~', "~0").replace('/', "~1");
+    let mut rest = path.strip_prefix('$').ok_or_else(unsupported)?;
+    let mut pointer = String::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(unsupported());
+            }
+            pointer += &format!("/{}", escape(&after[..end]));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(unsupported)?;
+            let key = &after[..end];
+            let key = if key.parse::<usize>().is_ok() {
+                key
+            } else {
+                key.strip_prefix(['\'', '"'])
+                    .and_then(|k| k.strip_suffix(['\'', '"']))
+                    .ok_or_else(unsupported)?
+            };
+            pointer += &format!("/{}", escape(key));
+            rest = &after[end + 1..];
+        } else {
+            return Err(unsupported());
+        }
+    }
+    Ok(pointer)
+}
+
+#[derive(Debug)]
+pub struct Generic {
+    config: GenericConfig,
+    /// For fields the portal translates, in order of preference.
+    field_languages: Vec<String>,
+    /// Whether the probe answered since the last failed fetch.
+    probed: AtomicBool,
+    /// Fields that had no value last time, so they're only reported once.
+    missing: Mutex<Vec<Field>>,
+}
+
+impl Generic {
+    pub fn new(config: GenericConfig, field_languages: Vec<String>) -> Self {
+        Self {
+            config,
+            field_languages,
+            probed: AtomicBool::new(false),
+            missing: Mutex::default(),
+        }
+    }
+
+    async fn fetch_all(
+        &self,
+        client: &reqwest::Client,
+        metrics: &Metrics,
+    ) -> Result<TripStatus, FetchError> {
+        let url = |path: &str| format!("{}{path}", self.config.base_url);
+        if let Some(probe) = self
+            .config
+            .probe
+            .as_deref()
+            .filter(|_| !self.probed.load(SeqCst))
+        {
+            timed(metrics, self.name(), "probe", get_text(client, &url(probe))).await?;
+            self.probed.store(true, SeqCst);
+        }
+        let responses = try_join_all(self.config.endpoints.iter().map(|(name, path)| async {
+            let url = url(path);
+            let body = timed(metrics, self.name(), name, get_text(client, &url)).await?;
+            // plain text, e.g. just the speed, can be mapped with `$`
+            let value = serde_json::from_str(&body)
+                .unwrap_or_else(|_| Value::String(body.trim().to_string()));
+            Ok::<_, reqwest::Error>((name.clone(), value))
+        }))
+        .await?;
+        Ok(self.status(&responses.into_iter().collect()))
+    }
+
+    fn status(&self, responses: &BTreeMap<String, Value>) -> TripStatus {
+        let mut missing = vec![];
+        let mut value = |field| {
+            let mapping: &Mapping = self.config.fields.get(&field)?;
+            let value = responses
+                .get(&mapping.endpoint)
+                .and_then(|json| json.pointer(&mapping.pointer))
+                .filter(|value| !value.is_null());
+            if value.is_none() {
+                missing.push(field);
+            }
+            value
+        };
+        let text = |value: &Value| match value {
+            Value::Number(number) => Some(number.to_string()),
+            value => localized(value, &self.field_languages).map(str::to_string),
+        };
+        let number = |value: &Value| match value {
+            Value::String(s) => s.trim().parse().ok(),
+            value => value.as_f64(),
+        };
+        // portals with full timestamps get them shown like everyone else's
+        let time = |value: &Value| {
+            let text = text(value)?;
+            Some(match DateTime::parse_from_rfc3339(&text) {
+                Ok(time) => time_of_day(time),
+                Err(_) => text,
+            })
+        };
+
+        let speed_kmh = value(Field::Speed).and_then(number);
+        let latitude = value(Field::Latitude).and_then(number);
+        let longitude = value(Field::Longitude).and_then(number);
+        let train_type = value(Field::TrainType).and_then(text);
+        let trip_number = value(Field::TripNumber).and_then(text);
+        let destination = value(Field::Destination).and_then(text);
+        let next_station = value(Field::NextStation).and_then(text);
+        let scheduled = value(Field::ScheduledArrival).and_then(time);
+        let forecast = value(Field::ForecastArrival).and_then(time);
+
+        let mut last = self.missing.lock();
+        if missing != *last {
+            if !missing.is_empty() {
+                let fields = missing.iter().map(Field::to_string).collect::<Vec<_>>();
+                eprintln!("{}: no value for {}", self.name(), fields.join(", "));
+            }
+            *last = missing;
+        }
+
+        TripStatus {
+            speed_kmh,
+            position: latitude
+                .zip(longitude)
+                .map(|(latitude, longitude)| Position {
+                    latitude,
+                    longitude,
+                }),
+            train_type,
+            trip_number,
+            destination,
+            next_stop: next_station.map(|name| Stop {
+                name,
+                arrival: Times {
+                    scheduled,
+                    forecast,
+                },
+                ..Default::default()
+            }),
+            stops: vec![],
+            ..Default::default()
+        }
+    }
+}
+
+impl Provider for Generic {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn dashboard_url(&self) -> &str {
+        &self.config.base_url
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        metrics: &'a Metrics,
+    ) -> BoxFuture<'a, Result<TripStatus, FetchError>> {
+        Box::pin(async move {
+            let result = self.fetch_all(client, metrics).await;
+            if result.is_err() {
+                // we may be on another train by the time it answers again
+                self.probed.store(false, SeqCst);
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+    use serde_json::json;
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+
+    const CONFIG: &str = r#"
+        name = "railnet"
+        base_url = "http://192.168.32.1/"
+        probe = "/api/speed"
+        endpoints = { speed = "/api/speed", train = "/api/train", combined = "/assets/modules/fis/combined.json" }
+        fields.speed = "speed:$"
+        fields.train_type = "train:$"
+        fields.trip_number = "combined:$.tripNumber"
+        fields.destination = "combined:$.destination"
+        fields.next_station = "combined:$.nextStation.name"
+        fields.scheduled_arrival = "combined:$['nextStation'].arrival.scheduled"
+        fields.forecast_arrival = "combined:/nextStation/arrival/forecast"
+        fields.latitude = "combined:$.position.latitude"
+    "#;
+
+    #[test]
+    fn json_paths() {
+        assert_eq!(json_path_to_pointer("$").unwrap(), "");
+        assert_eq!(
+            json_path_to_pointer("$.stations[2].name.de").unwrap(),
+            "/stations/2/name/de"
+        );
+        assert_eq!(json_path_to_pointer("$['a/b'][\"c\"]").unwrap(), "/a~1b/c");
+        assert!(json_path_to_pointer("$..name").is_err());
+        assert!(json_path_to_pointer("$.stations[*]").is_err());
+        assert!(json_path_to_pointer("$.stations[0").is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let config = toml::from_str::<GenericConfig>(CONFIG).unwrap();
+        assert_eq!(config.base_url, "http://192.168.32.1");
+        assert_eq!(
+            config.fields[&Field::NextStation],
+            Mapping {
+                endpoint: "combined".to_string(),
+                pointer: "/nextStation/name".to_string()
+            }
+        );
+
+        let error = toml::from_str::<GenericConfig>(
+            r#"
+            name = "broken"
+            base_url = "http://10.0.0.1"
+            endpoints = { status = "/status", trip = "/trip" }
+            fields.speed = "stats:/speed"
+            fields.next_station = "/next"
+            fields.destination = "trip:$..destination"
+            fields.trip_number = "trip:/number"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("fields that don't resolve:"), "{error}");
+        assert!(error.contains("speed (no endpoint `stats`)"), "{error}");
+        assert!(error.contains("next_station (which endpoint?"), "{error}");
+        assert!(error.contains("destination (`$..destination`"), "{error}");
+        assert!(!error.contains("trip_number"), "{error}");
+
+        // with a single endpoint, there's no need to name it
+        let config = toml::from_str::<GenericConfig>(
+            r#"
+            name = "simple"
+            base_url = "http://10.0.0.1"
+            endpoints = { status = "/status" }
+            fields.speed = "$.speed"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.fields[&Field::Speed].endpoint, "status");
+    }
+
+    #[tokio::test]
+    async fn fetch_from_mock_portal() {
+        let app = Router::new()
+            .route("/api/speed", get(|| async { "187.5" }))
+            // not JSON
+            .route("/api/train", get(|| async { "RJ\n" }))
+            .route(
+                "/assets/modules/fis/combined.json",
+                get(|| async {
+                    Json(json!({
+                        "tripNumber": 61,
+                        "destination": {"de": "Wien Hbf", "en": "Vienna Central Station"},
+                        "nextStation": {
+                            "name": {"de": "Linz Hbf"},
+                            "arrival": {"scheduled": "14:05", "forecast": "14:09"},
+                        },
+                    }))
+                }),
+            );
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let mut config = toml::from_str::<GenericConfig>(CONFIG).unwrap();
+        config.base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let generic = Generic::new(config, vec!["en".to_string()]);
+        let metrics = Metrics::default();
+        let status = generic
+            .fetch(&reqwest::Client::new(), &metrics)
+            .await
+            .unwrap();
+        assert_eq!(status.speed_kmh, Some(187.5));
+        assert_eq!(status.train().as_deref(), Some("RJ 61"));
+        assert_eq!(
+            status.destination.as_deref(),
+            Some("Vienna Central Station")
+        );
+        assert_eq!(status.next_stop.as_ref().unwrap().name, "Linz Hbf");
+        assert_eq!(status.delay_minutes(), Some(4));
+        assert_eq!(status.position, None);
+        assert_eq!(*generic.missing.lock(), [Field::Latitude]);
+        assert!(generic.probed.load(SeqCst));
+        assert!(metrics
+            .render(Some("railnet"), Some(&status))
+            .contains("portal_request_errors_total{portal=\"railnet\",endpoint=\"probe\"} 0\n"));
+    }
+}
@@ -0,0 +1,103 @@
+//! The onboard portals we know how to read, each turning whatever its portal
+//! serves into a [`TripStatus`].
+
+pub mod generic;
+pub mod oebb;
+pub mod sncf;
+pub mod westbahn;
+
+use crate::{config::Config, metrics::Metrics, trip::TripStatus};
+use chrono::{DateTime, Local, TimeZone};
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use std::{
+    error::Error,
+    fmt::Debug,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Portals answer within milliseconds when we're on their train; anything
+/// slower is another train's portal we can't reach.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type FetchError = Box<dyn Error + Send + Sync>;
+
+pub trait Provider: Debug + Send + Sync {
+    /// Short and stable, e.g. `"oebb"`, for metrics labels and logs.
+    fn name(&self) -> &str;
+
+    /// The portal's own page, for "Open dashboard".
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn dashboard_url(&self) -> &str;
+
+    /// Everything the portal knows right now. Fails when we aren't on one of
+    /// its trains.
+    fn fetch<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        metrics: &'a Metrics,
+    ) -> BoxFuture<'a, Result<TripStatus, FetchError>>;
+}
+
+/// Every provider, in the order they're tried when we don't know yet which
+/// train we're on. The ones from the config come first, so they can stand in
+/// for a built-in one.
+pub fn all(config: &Config) -> Vec<Arc<dyn Provider>> {
+    let configured = config.providers.iter().map(|provider| {
+        let generic = generic::Generic::new(provider.clone(), config.field_languages.clone());
+        Arc::new(generic) as Arc<dyn Provider>
+    });
+    configured
+        .chain([
+            Arc::new(oebb::Oebb::new(config.field_languages.clone())) as Arc<dyn Provider>,
+            Arc::new(westbahn::Westbahn::default()),
+            Arc::new(sncf::Sncf::default()),
+        ])
+        .collect()
+}
+
+/// Times `request` for the `portal_request_duration_seconds` histogram.
+pub async fn timed<T, E>(
+    metrics: &Metrics,
+    portal: &str,
+    endpoint: &str,
+    request: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let result = request.await;
+    metrics.observe_request(portal, endpoint, start.elapsed(), result.is_ok());
+    result
+}
+
+/// For portals with full timestamps, as everything else only knows the time
+/// of day as ÖBB shows it.
+fn time_of_day<Tz: TimeZone>(time: DateTime<Tz>) -> String {
+    time.with_timezone(&Local).format("%H:%M").to_string()
+}
+
+async fn get_text(client: &reqwest::Client, url: &str) -> Result<String, reqwest::Error> {
+    client
+        .get(url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+async fn get_json<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<T, reqwest::Error> {
+    client
+        .get(url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
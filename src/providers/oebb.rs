@@ -0,0 +1,266 @@
+use super::{get_json, get_text, timed, FetchError, Provider};
+use crate::{
+    i18n::localized,
+    metrics::Metrics,
+    trip::{Connection, Position, Stop, Times, TripStatus},
+};
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+pub const DASHBOARD_URL: &str = "http://192.168.32.1";
+
+/// ÖBB's Railnet portal on Railjets and Cityjets.
+#[derive(Debug)]
+pub struct Oebb {
+    base_url: String,
+    /// For the fields ÖBB translates, in order of preference.
+    field_languages: Vec<String>,
+}
+
+impl Oebb {
+    pub fn new(field_languages: Vec<String>) -> Self {
+        Self {
+            base_url: DASHBOARD_URL.to_string(),
+            field_languages,
+        }
+    }
+}
+
+impl Provider for Oebb {
+    fn name(&self) -> &str {
+        "oebb"
+    }
+
+    fn dashboard_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// `/api/speed` and `combined.json`. Without a speed, e.g. on trains
+    /// whose portal doesn't have the endpoint, it's up to GPS.
+    fn fetch<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        metrics: &'a Metrics,
+    ) -> BoxFuture<'a, Result<TripStatus, FetchError>> {
+        Box::pin(async move {
+            let speed = timed(
+                metrics,
+                self.name(),
+                "speed",
+                get_text(client, &format!("{}/api/speed", self.base_url)),
+            )
+            .await
+            .ok();
+            let combined = timed(
+                metrics,
+                self.name(),
+                "combined",
+                get_json::<Value>(
+                    client,
+                    &format!("{}/assets/modules/fis/combined.json", self.base_url),
+                ),
+            )
+            .await?;
+            Ok(parse(speed.as_deref(), &combined, &self.field_languages))
+        })
+    }
+}
+
+fn parse(speed: Option<&str>, combined: &Value, field_languages: &[String]) -> TripStatus {
+    let stops = combined
+        .get("stations")
+        .and_then(Value::as_array)
+        .map(|stations| {
+            stations
+                .iter()
+                .filter_map(|s| stop(s, field_languages))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    TripStatus {
+        speed_kmh: speed.and_then(|speed| speed.trim().parse().ok()),
+        position: position(combined),
+        train_type: string_at(combined, "/trainType"),
+        trip_number: string_at(combined, "/tripNumber"),
+        destination: combined
+            .get("destination")
+            .and_then(|d| localized(d, field_languages))
+            .map(str::to_string),
+        next_stop: combined
+            .get("nextStation")
+            .and_then(|s| stop(s, field_languages)),
+        stops,
+        ..Default::default()
+    }
+}
+
+fn stop(station: &Value, field_languages: &[String]) -> Option<Stop> {
+    let name = localized(station.get("name")?, field_languages)?.to_string();
+    Some(Stop {
+        name,
+        arrival: times(station, "arrival"),
+        departure: times(station, "departure"),
+        position: position(station),
+        km: station.get("km").and_then(Value::as_f64),
+        connections: station
+            .get("connections")
+            .and_then(Value::as_array)
+            .map(|connections| {
+                connections
+                    .iter()
+                    .filter_map(|c| connection(c, field_languages))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+/// What the portal lists as a station's `connections`, which it only does for
+/// the next few stations and bigger ones.
+fn connection(value: &Value, field_languages: &[String]) -> Option<Connection> {
+    Some(Connection {
+        line: string_at(value, "/lineName")?,
+        destination: value
+            .get("destination")
+            .and_then(|d| localized(d, field_languages))
+            .map(str::to_string),
+        departure: times(value, "departure"),
+        platform: string_at(value, "/track"),
+    })
+}
+
+fn times(value: &Value, key: &str) -> Times {
+    Times {
+        scheduled: string_at(value, &format!("/{key}/scheduled")),
+        forecast: string_at(value, &format!("/{key}/forecast")),
+    }
+}
+
+/// `latitude` and `longitude`, which the train has and some stations do.
+fn position(value: &Value) -> Option<Position> {
+    Some(Position {
+        latitude: value.get("latitude")?.as_f64()?,
+        longitude: value.get("longitude")?.as_f64()?,
+    })
+}
+
+fn string_at(value: &Value, pointer: &str) -> Option<String> {
+    value
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gps::GpsSpeed, trip::SpeedSource};
+    use axum::{http::StatusCode, routing::get, Json, Router};
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use std::{
+        net::Ipv4Addr,
+        sync::{
+            atomic::{AtomicUsize, Ordering::SeqCst},
+            Arc,
+        },
+    };
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn combined_json() {
+        let combined = json!({
+            "trainType": "RJ",
+            "tripNumber": "61",
+            "latitude": 48.2066,
+            "longitude": 14.2858,
+            "destination": {"de": "Wien Hbf", "en": "Vienna Central Station", "all": "Wien Hbf"},
+            "nextStation": {
+                "name": {"de": "Linz Hbf", "all": "Linz Hbf"},
+                "arrival": {"scheduled": "14:05", "forecast": "14:09"},
+                "departure": {"scheduled": "14:07", "forecast": "14:10"},
+                "connections": [
+                    {"lineName": "REX 41", "destination": {"de": "Summerau"}, "departure": {"scheduled": "14:14", "forecast": "14:16"}, "track": "7"},
+                    {"destination": {"de": "Nirgendwo"}},
+                ],
+            },
+            "stations": [
+                {"name": {"de": "Salzburg Hbf"}, "departure": {"scheduled": "13:08", "forecast": "13:08"}},
+                {"name": {"de": "Linz Hbf"}, "arrival": {"scheduled": "14:05", "forecast": "14:09"}, "km": 189.4, "latitude": 48.2904, "longitude": 14.2913},
+                {"id": 3},
+            ],
+        });
+        let status = parse(Some("187\n"), &combined, &["en".to_string()]);
+
+        assert_eq!(status.speed_kmh, Some(187.0));
+        assert_eq!(status.position.unwrap().latitude, 48.2066);
+        assert_eq!(status.train().as_deref(), Some("RJ 61"));
+        assert_eq!(
+            status.destination.as_deref(),
+            Some("Vienna Central Station")
+        );
+        assert_eq!(status.next_stop.as_ref().unwrap().name, "Linz Hbf");
+        assert_eq!(status.delay_minutes(), Some(4));
+        let connections = &status.next_stop.as_ref().unwrap().connections;
+        assert_eq!(
+            connections,
+            &[Connection {
+                line: "REX 41".to_string(),
+                destination: Some("Summerau".to_string()),
+                departure: Times {
+                    scheduled: Some("14:14".to_string()),
+                    forecast: Some("14:16".to_string()),
+                },
+                platform: Some("7".to_string()),
+            }]
+        );
+        assert_eq!(status.stops.len(), 2);
+        assert_eq!(status.stops[0].arrival.best(), None);
+        assert_eq!(status.stops[0].departure.best(), Some("13:08"));
+        assert_eq!(status.stops[0].position, None);
+        assert_eq!(status.stops[1].km, Some(189.4));
+        assert_eq!(status.stops[1].position.unwrap().longitude, 14.2913);
+    }
+
+    #[tokio::test]
+    async fn speed_from_gps_without_the_speed_endpoint() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route("/api/speed", get(|| async { StatusCode::NOT_FOUND }))
+            .route(
+                "/assets/modules/fis/combined.json",
+                get(move || async move {
+                    // 0.5 km further east every poll
+                    let poll = polls.fetch_add(1, SeqCst) as f64;
+                    Json(json!({
+                        "trainType": "RJ",
+                        "tripNumber": "61",
+                        "latitude": 48.0,
+                        "longitude": 14.0 + poll * 0.5 / 74.4,
+                    }))
+                }),
+            );
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let oebb = Oebb {
+            base_url,
+            field_languages: vec![],
+        };
+        let client = reqwest::Client::new();
+        let metrics = Metrics::default();
+        let mut gps = GpsSpeed::default();
+        let start = Utc::now();
+        let mut status = TripStatus::default();
+        for poll in 0..3 {
+            status = oebb.fetch(&client, &metrics).await.unwrap();
+            assert_eq!(status.train().as_deref(), Some("RJ 61"));
+            gps.observe(&mut status, start + Duration::seconds(poll * 10));
+        }
+        // 0.5 km in 10 s
+        assert!((status.speed_kmh.unwrap() - 180.0).abs() < 1.0);
+        assert_eq!(status.speed_source, SpeedSource::Gps);
+    }
+}
@@ -0,0 +1,247 @@
+use super::{get_json, time_of_day, timed, FetchError, Provider};
+use crate::{
+    metrics::Metrics,
+    trip::{Position, Stop, Times, TripStatus},
+};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+pub const DASHBOARD_URL: &str = "https://wifi.sncf";
+
+/// SNCF's portal on TGV inOui and Ouigo trains.
+#[derive(Debug)]
+pub struct Sncf {
+    base_url: String,
+}
+
+impl Default for Sncf {
+    fn default() -> Self {
+        Self {
+            base_url: DASHBOARD_URL.to_string(),
+        }
+    }
+}
+
+/// `/router/api/train/gps`.
+#[derive(Debug, Deserialize)]
+struct Gps {
+    /// Whether the train has a fix, the rest is meaningless otherwise.
+    #[serde(default)]
+    success: bool,
+    /// Of the fix, in seconds since the epoch.
+    timestamp: Option<i64>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    /// In m/s.
+    speed: Option<f64>,
+}
+
+/// `/router/api/train/details`.
+#[derive(Debug, Deserialize)]
+struct Details {
+    number: Option<String>,
+    #[serde(default)]
+    stops: Vec<DetailsStop>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Coordinates {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DetailsStop {
+    label: String,
+    coordinates: Option<Coordinates>,
+    theoric_date: Option<DateTime<Utc>>,
+    real_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    is_removed: bool,
+}
+
+impl Provider for Sncf {
+    fn name(&self) -> &str {
+        "sncf"
+    }
+
+    fn dashboard_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        metrics: &'a Metrics,
+    ) -> BoxFuture<'a, Result<TripStatus, FetchError>> {
+        Box::pin(async move {
+            let gps = format!("{}/router/api/train/gps", self.base_url);
+            let details = format!("{}/router/api/train/details", self.base_url);
+            let (gps, details) = tokio::try_join!(
+                timed(metrics, self.name(), "gps", get_json::<Gps>(client, &gps)),
+                timed(
+                    metrics,
+                    self.name(),
+                    "details",
+                    get_json::<Details>(client, &details)
+                ),
+            )?;
+            Ok(parse(gps, details, Utc::now()))
+        })
+    }
+}
+
+/// SNCF has one time per stop, which is when the train arrives, or leaves
+/// the first stop.
+fn parse(gps: Gps, details: Details, now: DateTime<Utc>) -> TripStatus {
+    let fixed_at = gps
+        .timestamp
+        .filter(|_| gps.success)
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .unwrap_or(now);
+    let stops = details
+        .stops
+        .iter()
+        .filter(|stop| !stop.is_removed)
+        .collect::<Vec<_>>();
+    let next_stop = stops
+        .iter()
+        .position(|stop| stop.real_date.or(stop.theoric_date) > Some(fixed_at));
+    let last = stops.len().saturating_sub(1);
+    let stops = stops
+        .iter()
+        .enumerate()
+        .map(|(index, stop)| {
+            let times = Times {
+                scheduled: stop.theoric_date.map(time_of_day),
+                forecast: stop.real_date.map(time_of_day),
+            };
+            Stop {
+                name: stop.label.clone(),
+                arrival: if index > 0 {
+                    times.clone()
+                } else {
+                    Times::default()
+                },
+                departure: if index < last {
+                    times
+                } else {
+                    Times::default()
+                },
+                position: stop.coordinates.as_ref().map(|c| Position {
+                    latitude: c.latitude,
+                    longitude: c.longitude,
+                }),
+                km: None,
+                connections: vec![],
+            }
+        })
+        .collect::<Vec<_>>();
+
+    TripStatus {
+        speed_kmh: gps.speed.filter(|_| gps.success).map(|ms| ms * 3.6),
+        position: match (gps.success, gps.latitude, gps.longitude) {
+            (true, Some(latitude), Some(longitude)) => Some(Position {
+                latitude,
+                longitude,
+            }),
+            _ => None,
+        },
+        train_type: None,
+        trip_number: details.number,
+        destination: stops.last().map(|stop| stop.name.clone()),
+        next_stop: next_stop.map(|index| stops[index].clone()),
+        stops,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+
+    const GPS: &str = include_str!("fixtures/sncf-gps.json");
+    const DETAILS: &str = include_str!("fixtures/sncf-details.json");
+
+    fn local(rfc3339: &str) -> Option<String> {
+        Some(time_of_day(DateTime::parse_from_rfc3339(rfc3339).unwrap()))
+    }
+
+    #[test]
+    fn gps_and_details() {
+        let gps = serde_json::from_str(GPS).unwrap();
+        let details = serde_json::from_str(DETAILS).unwrap();
+        let status = parse(gps, details, Utc::now());
+
+        assert_eq!(status.speed_kmh.map(f64::round), Some(301.0));
+        assert_eq!(status.position.unwrap().longitude, 5.0413);
+        assert_eq!(status.train().as_deref(), Some("6611"));
+        assert_eq!(
+            status.destination.as_deref(),
+            Some("Marseille Saint-Charles")
+        );
+        // Chalon-sur-Saône is cancelled
+        let names = status
+            .stops
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "Paris Gare de Lyon",
+                "Dijon",
+                "Lyon Part-Dieu",
+                "Marseille Saint-Charles"
+            ]
+        );
+
+        // the fix is from 13:12 UTC, before the train gets to Dijon
+        let dijon = status.next_stop.as_ref().unwrap();
+        assert_eq!(dijon.name, "Dijon");
+        assert_eq!(dijon.arrival.scheduled, local("2024-03-01T13:37:00Z"));
+        assert_eq!(status.delay_minutes(), Some(6));
+        assert_eq!(status.origin(), Some(0));
+        assert_eq!(status.stops[0].arrival, Times::default());
+        assert_eq!(status.stops[3].departure, Times::default());
+        assert_eq!(status.stops[1].position.unwrap().latitude, 47.3233);
+    }
+
+    #[test]
+    fn no_fix() {
+        let gps = serde_json::from_str(r#"{"success": false, "fix": -1, "speed": 0}"#).unwrap();
+        let details = serde_json::from_str(DETAILS).unwrap();
+        let now = "2024-03-01T14:00:00Z".parse().unwrap();
+        let status = parse(gps, details, now);
+
+        assert_eq!(status.speed_kmh, None);
+        assert_eq!(status.position, None);
+        assert_eq!(status.next_stop.unwrap().name, "Lyon Part-Dieu");
+    }
+
+    #[tokio::test]
+    async fn fetch_from_mock_portal() {
+        let app = Router::new()
+            .route("/router/api/train/gps", get(|| async { GPS }))
+            .route("/router/api/train/details", get(|| async { DETAILS }));
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let sncf = Sncf { base_url };
+        let metrics = Metrics::default();
+        let status = sncf.fetch(&reqwest::Client::new(), &metrics).await.unwrap();
+        assert_eq!(status.trip_number.as_deref(), Some("6611"));
+        assert_eq!(status.stops.len(), 4);
+        let out = metrics.render(Some("sncf"), Some(&status));
+        assert!(out.contains("portal_request_errors_total{portal=\"sncf\",endpoint=\"gps\"} 0\n"));
+        assert!(
+            out.contains("portal_request_errors_total{portal=\"sncf\",endpoint=\"details\"} 0\n")
+        );
+    }
+}
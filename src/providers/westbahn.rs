@@ -0,0 +1,198 @@
+use super::{get_json, time_of_day, timed, FetchError, Provider};
+use crate::{
+    metrics::Metrics,
+    trip::{Position, Stop, Times, TripStatus},
+};
+use chrono::{DateTime, FixedOffset};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+pub const DASHBOARD_URL: &str = "http://wifi.westbahn.at";
+
+/// The Westbahn portal on its double-deckers between Vienna and Salzburg.
+#[derive(Debug)]
+pub struct Westbahn {
+    base_url: String,
+}
+
+impl Default for Westbahn {
+    fn default() -> Self {
+        Self {
+            base_url: DASHBOARD_URL.to_string(),
+        }
+    }
+}
+
+/// `/api/trip`, which has everything in one go.
+#[derive(Debug, Deserialize)]
+struct Trip {
+    train: Train,
+    /// In km/h.
+    speed: Option<f64>,
+    position: Option<LatLon>,
+    #[serde(default)]
+    stops: Vec<TripStop>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Train {
+    category: Option<String>,
+    number: Option<String>,
+    destination: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LatLon {
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TripStop {
+    name: String,
+    arrival: Option<TripTimes>,
+    departure: Option<TripTimes>,
+    /// Whether the train has left it.
+    #[serde(default)]
+    passed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TripTimes {
+    planned: Option<DateTime<FixedOffset>>,
+    expected: Option<DateTime<FixedOffset>>,
+}
+
+impl Provider for Westbahn {
+    fn name(&self) -> &str {
+        "westbahn"
+    }
+
+    fn dashboard_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        metrics: &'a Metrics,
+    ) -> BoxFuture<'a, Result<TripStatus, FetchError>> {
+        Box::pin(async move {
+            let trip = timed(
+                metrics,
+                self.name(),
+                "trip",
+                get_json::<Trip>(client, &format!("{}/api/trip", self.base_url)),
+            )
+            .await?;
+            Ok(trip.into())
+        })
+    }
+}
+
+impl From<Trip> for TripStatus {
+    fn from(trip: Trip) -> Self {
+        let next_stop = trip.stops.iter().position(|stop| !stop.passed);
+        let stops = trip.stops.into_iter().map(Stop::from).collect::<Vec<_>>();
+        Self {
+            speed_kmh: trip.speed,
+            position: trip.position.map(|p| Position {
+                latitude: p.lat,
+                longitude: p.lon,
+            }),
+            train_type: trip.train.category,
+            trip_number: trip.train.number,
+            destination: trip.train.destination,
+            next_stop: next_stop.map(|index| stops[index].clone()),
+            stops,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<TripStop> for Stop {
+    fn from(stop: TripStop) -> Self {
+        Self {
+            name: stop.name,
+            arrival: stop.arrival.map(Times::from).unwrap_or_default(),
+            departure: stop.departure.map(Times::from).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<TripTimes> for Times {
+    fn from(times: TripTimes) -> Self {
+        Self {
+            scheduled: times.planned.map(time_of_day),
+            forecast: times.expected.map(time_of_day),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::get, Router};
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+
+    const TRIP: &str = include_str!("fixtures/westbahn-trip.json");
+
+    fn local(rfc3339: &str) -> Option<String> {
+        Some(time_of_day(DateTime::parse_from_rfc3339(rfc3339).unwrap()))
+    }
+
+    #[test]
+    fn trip() {
+        let status = TripStatus::from(serde_json::from_str::<Trip>(TRIP).unwrap());
+
+        assert_eq!(status.speed_kmh, Some(187.3));
+        assert_eq!(
+            status.position,
+            Some(Position {
+                latitude: 48.2066,
+                longitude: 14.2858
+            })
+        );
+        assert_eq!(status.train().as_deref(), Some("WB 917"));
+        assert_eq!(status.destination.as_deref(), Some("Wien Westbahnhof"));
+        assert_eq!(status.stops.len(), 4);
+        assert_eq!(status.origin(), Some(0));
+
+        let linz = status.next_stop.as_ref().unwrap();
+        assert_eq!(linz.name, "Linz Hbf");
+        assert_eq!(linz.arrival.scheduled, local("2024-03-01T14:05:00+01:00"));
+        assert_eq!(linz.arrival.forecast, local("2024-03-01T14:09:00+01:00"));
+        assert_eq!(status.delay_minutes(), Some(4));
+        assert_eq!(status.stops[0].arrival, Times::default());
+        assert_eq!(status.stops[2].arrival.forecast, None);
+    }
+
+    #[tokio::test]
+    async fn fetch_from_mock_portal() {
+        let app = Router::new().route("/api/trip", get(|| async { TRIP }));
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let westbahn = Westbahn { base_url };
+        let client = reqwest::Client::new();
+        let metrics = Metrics::default();
+        let status = westbahn.fetch(&client, &metrics).await.unwrap();
+        assert_eq!(status.train().as_deref(), Some("WB 917"));
+        assert!(metrics
+            .render(Some("westbahn"), Some(&status))
+            .contains("portal_request_errors_total{portal=\"westbahn\",endpoint=\"trip\"} 0\n"));
+
+        let app = Router::new().route(
+            "/api/trip",
+            get(|| async { (StatusCode::SERVICE_UNAVAILABLE, "<html>maintenance</html>") }),
+        );
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let westbahn = Westbahn { base_url };
+        assert!(westbahn.fetch(&client, &metrics).await.is_err());
+    }
+}
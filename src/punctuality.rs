@@ -0,0 +1,306 @@
+use crate::{
+    cli::DateRange,
+    config::Config,
+    recorder::{load_journeys, Journey},
+    trip::{Stop, PUNCTUAL_WITHIN_MINUTES},
+};
+use std::{collections::BTreeMap, error::Error};
+
+/// How many segments `traveltracker punctuality` lists per train or route.
+const WORST_SEGMENTS: usize = 3;
+
+/// How a train number or a route did over the recorded journeys, going by
+/// the delays the portal showed last on each.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Punctuality {
+    pub journeys: usize,
+    /// Journeys we know the delay at the final station of.
+    pub arrivals: usize,
+    pub on_time: usize,
+    /// In the order the train calls at them.
+    pub stations: Vec<StationDelays>,
+    /// Worst first.
+    pub segments: Vec<SegmentDelays>,
+}
+
+/// Delays at one station, in minutes, one per journey.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationDelays {
+    pub name: String,
+    pub delays: Vec<i64>,
+}
+
+/// Minutes gained between leaving one station and arriving at the next, one
+/// per journey.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentDelays {
+    pub from: String,
+    pub to: String,
+    pub gained: Vec<i64>,
+}
+
+impl Punctuality {
+    pub fn on_time_percent(&self) -> Option<f64> {
+        (self.arrivals > 0).then(|| self.on_time as f64 * 100.0 / self.arrivals as f64)
+    }
+
+    /// The segments where the train usually loses time.
+    pub fn worst_segments(&self) -> impl Iterator<Item = &SegmentDelays> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.mean() > 0.0)
+            .take(WORST_SEGMENTS)
+    }
+
+    fn add(&mut self, journey: &Journey) {
+        self.journeys += 1;
+        let stops = travelled(journey);
+        if let Some(delay) = stops.last().and_then(|stop| stop.arrival.delay_minutes()) {
+            self.arrivals += 1;
+            if delay <= PUNCTUAL_WITHIN_MINUTES {
+                self.on_time += 1;
+            }
+        }
+
+        for stop in stops {
+            let Some(delay) = stop
+                .arrival
+                .delay_minutes()
+                .or(stop.departure.delay_minutes())
+            else {
+                continue;
+            };
+            match self.stations.iter_mut().find(|s| s.name == stop.name) {
+                Some(station) => station.delays.push(delay),
+                None => self.stations.push(StationDelays {
+                    name: stop.name.clone(),
+                    delays: vec![delay],
+                }),
+            }
+        }
+
+        for pair in stops.windows(2) {
+            let left = pair[0]
+                .departure
+                .delay_minutes()
+                .or(pair[0].arrival.delay_minutes());
+            let (Some(left), Some(arrived)) = (left, pair[1].arrival.delay_minutes()) else {
+                continue;
+            };
+            let segment = self
+                .segments
+                .iter_mut()
+                .find(|s| s.from == pair[0].name && s.to == pair[1].name);
+            match segment {
+                Some(segment) => segment.gained.push(arrived - left),
+                None => self.segments.push(SegmentDelays {
+                    from: pair[0].name.clone(),
+                    to: pair[1].name.clone(),
+                    gained: vec![arrived - left],
+                }),
+            }
+        }
+        self.segments.sort_by(|a, b| b.mean().total_cmp(&a.mean()));
+    }
+}
+
+impl StationDelays {
+    pub fn mean(&self) -> f64 {
+        mean(&self.delays)
+    }
+
+    /// The delay `percent` of the journeys stayed within.
+    pub fn percentile(&self, percent: f64) -> i64 {
+        let mut delays = self.delays.clone();
+        delays.sort_unstable();
+        let rank = (percent / 100.0 * delays.len() as f64).ceil() as usize;
+        delays[rank.clamp(1, delays.len()) - 1]
+    }
+}
+
+impl SegmentDelays {
+    pub fn mean(&self) -> f64 {
+        mean(&self.gained)
+    }
+}
+
+fn mean(minutes: &[i64]) -> f64 {
+    minutes.iter().sum::<i64>() as f64 / minutes.len().max(1) as f64
+}
+
+/// The stops up to where we got off, whose delays are as final as they get.
+fn travelled(journey: &Journey) -> &[Stop] {
+    let end = journey
+        .to()
+        .and_then(|to| journey.stops.iter().position(|stop| stop.name == to))
+        .map_or(journey.stops.len(), |index| index + 1);
+    &journey.stops[..end]
+}
+
+/// Per train, e.g. `"RJ 61"`.
+pub fn by_train(journeys: &[Journey]) -> BTreeMap<String, Punctuality> {
+    group(journeys, Journey::train)
+}
+
+/// Per route, e.g. `"Salzburg Hbf – Wien Hbf"`.
+pub fn by_route(journeys: &[Journey]) -> BTreeMap<String, Punctuality> {
+    group(journeys, |journey| {
+        Some(format!("{} – {}", journey.from()?, journey.to()?))
+    })
+}
+
+fn group(
+    journeys: &[Journey],
+    key: impl Fn(&Journey) -> Option<String>,
+) -> BTreeMap<String, Punctuality> {
+    let mut groups = BTreeMap::<String, Punctuality>::new();
+    for journey in journeys {
+        if let Some(key) = key(journey) {
+            groups.entry(key).or_default().add(journey);
+        }
+    }
+    groups
+}
+
+/// `traveltracker punctuality`: how the recorded journeys in `range` went,
+/// per train or per route, only `only` if given.
+pub fn print(
+    config: &Config,
+    range: &DateRange,
+    per_route: bool,
+    only: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let journeys = load_journeys(&config.journeys_dir()?)?
+        .into_iter()
+        .filter(|journey| range.contains(journey.started_at))
+        .collect::<Vec<_>>();
+    let groups = if per_route {
+        by_route(&journeys)
+    } else {
+        by_train(&journeys)
+    };
+    let groups = groups
+        .iter()
+        .filter(|(name, _)| only.is_none_or(|only| *name == only))
+        .collect::<Vec<_>>();
+    if groups.is_empty() {
+        return Err("no recorded journeys to go by".into());
+    }
+    for (name, punctuality) in groups {
+        print!("{}", summary(name, punctuality));
+    }
+    Ok(())
+}
+
+fn summary(name: &str, punctuality: &Punctuality) -> String {
+    let on_time = punctuality
+        .on_time_percent()
+        .map(|percent| format!(", {percent:.0}% on time"))
+        .unwrap_or_default();
+    let mut out = format!("{name}: {} journeys{on_time}\n", punctuality.journeys);
+    let width = punctuality
+        .stations
+        .iter()
+        .map(|station| station.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for station in &punctuality.stations {
+        out += &format!(
+            "  {:width$}  {:+5.1} min on average, {:+} or better 90% of the time ({})\n",
+            station.name,
+            station.mean(),
+            station.percentile(90.0),
+            station.delays.len(),
+        );
+    }
+    for segment in punctuality.worst_segments() {
+        out += &format!(
+            "  loses {:.1} min from {} to {}\n",
+            segment.mean(),
+            segment.from,
+            segment.to
+        );
+    }
+    out + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::TripEvent, trip::Times};
+    use chrono::{Duration, Utc};
+
+    fn journey(delays: [(i64, i64); 3]) -> Journey {
+        let stop = |name: &str, (arrival, departure): (i64, i64)| {
+            let times = |delay: i64| Times {
+                scheduled: Some("12:00".to_string()),
+                forecast: Some(format!("12:{delay:02}")),
+            };
+            Stop {
+                name: name.to_string(),
+                arrival: times(arrival),
+                departure: times(departure),
+                ..Default::default()
+            }
+        };
+        let start = Utc::now() - Duration::hours(3);
+        Journey {
+            train_type: Some("RJ".to_string()),
+            trip_number: Some("61".to_string()),
+            destination: Some("Wien Hbf".to_string()),
+            boarded_at: Some("Salzburg Hbf".to_string()),
+            started_at: start,
+            ended_at: start + Duration::hours(3),
+            stops: vec![
+                stop("Salzburg Hbf", delays[0]),
+                stop("Linz Hbf", delays[1]),
+                stop("Wien Hbf", delays[2]),
+            ],
+            events: vec![TripEvent::Departure {
+                time: start,
+                station: Some("Salzburg Hbf".to_string()),
+            }],
+            samples: vec![],
+        }
+    }
+
+    #[test]
+    fn statistics() {
+        let journeys = [
+            journey([(0, 0), (2, 3), (4, 4)]),
+            journey([(0, 1), (8, 8), (12, 12)]),
+            journey([(0, 0), (0, 0), (1, 1)]),
+        ];
+        let trains = by_train(&journeys);
+        let rj61 = &trains["RJ 61"];
+        assert_eq!(rj61.journeys, 3);
+        assert_eq!((rj61.arrivals, rj61.on_time), (3, 2));
+        assert!((rj61.on_time_percent().unwrap() - 66.7).abs() < 0.1);
+
+        let names = rj61
+            .stations
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Salzburg Hbf", "Linz Hbf", "Wien Hbf"]);
+        let wien = &rj61.stations[2];
+        assert!((wien.mean() - 17.0 / 3.0).abs() < 0.01);
+        assert_eq!(wien.percentile(90.0), 12);
+        assert_eq!(wien.percentile(50.0), 4);
+
+        // 2, 7 and 0 from Salzburg, 1, 4 and 1 on to Wien
+        let worst = rj61.worst_segments().collect::<Vec<_>>();
+        assert_eq!(worst[0].from, "Salzburg Hbf");
+        assert!((worst[0].mean() - 3.0).abs() < 0.01);
+        assert_eq!(worst[1].to, "Wien Hbf");
+
+        let routes = by_route(&journeys);
+        assert_eq!(routes["Salzburg Hbf – Wien Hbf"].journeys, 3);
+
+        let summary = summary("RJ 61", rj61);
+        assert!(summary.starts_with("RJ 61: 3 journeys, 67% on time\n"));
+        assert!(summary
+            .contains("  Wien Hbf       +5.7 min on average, +12 or better 90% of the time (3)\n"));
+        assert!(summary.contains("  loses 3.0 min from Salzburg Hbf to Linz Hbf\n"));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::{
+    events::TripEvent,
+    state::{Sample, SharedState},
+    trip::{train_name, Stop, TripStatus},
+};
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+/// Longer than this without hearing from the portal and it's a new journey,
+/// even on the same train (e.g. the same commute the next day).
+const JOURNEY_GAP_MINUTES: i64 = 30;
+/// Longer than this between samples and we don't guess how far the train went
+/// in between.
+const MAX_SAMPLE_GAP_SECONDS: i64 = 120;
+/// Keeps a day on the train to a few thousand samples.
+const SAMPLE_INTERVAL_SECONDS: i64 = 5;
+const SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// One ride on one train, as recorded from the portal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Journey {
+    pub train_type: Option<String>,
+    pub trip_number: Option<String>,
+    pub destination: Option<String>,
+    /// Where we were when recording started, the train's origin for all we
+    /// know.
+    pub boarded_at: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// When we last heard from the portal.
+    pub ended_at: DateTime<Utc>,
+    /// The stop list as the portal last showed it, forecasts included.
+    pub stops: Vec<Stop>,
+    /// Arrivals, departures and delay changes.
+    pub events: Vec<TripEvent>,
+    pub samples: Vec<Sample>,
+}
+
+impl Journey {
+    fn new(status: &TripStatus, now: DateTime<Utc>) -> Self {
+        // standing at a platform, the portal already shows it as the next stop
+        let standing = status.speed_kmh.is_some_and(|kmh| kmh < 10.0);
+        let boarded_at = match status.origin() {
+            Some(origin) if !standing => Some(status.stops[origin].name.clone()),
+            _ => status.next_stop.as_ref().map(|s| s.name.clone()),
+        };
+        Self {
+            train_type: status.train_type.clone(),
+            trip_number: status.trip_number.clone(),
+            destination: status.destination.clone(),
+            boarded_at,
+            started_at: now,
+            ended_at: now,
+            stops: status.stops.clone(),
+            events: vec![],
+            samples: vec![],
+        }
+    }
+
+    /// E.g. `"RJ 61"`.
+    pub fn train(&self) -> Option<String> {
+        train_name(self.train_type.as_deref(), self.trip_number.as_deref())
+    }
+
+    /// Where the train first left from while we were on it.
+    pub fn from(&self) -> Option<&str> {
+        self.events
+            .iter()
+            .find_map(|event| match event {
+                TripEvent::Departure { station, .. } => Some(station.as_deref()),
+                _ => None,
+            })
+            .unwrap_or(self.boarded_at.as_deref())
+    }
+
+    /// Where we got off, or where the train is headed if it hasn't stopped
+    /// for good yet.
+    pub fn to(&self) -> Option<&str> {
+        match self.final_arrival() {
+            Some((_, station)) => station,
+            None => self.destination.as_deref(),
+        }
+    }
+
+    /// When the train first left while we were on it.
+    pub fn departed_at(&self) -> DateTime<Utc> {
+        self.events
+            .iter()
+            .find_map(|event| match event {
+                TripEvent::Departure { time, .. } => Some(*time),
+                _ => None,
+            })
+            .unwrap_or(self.started_at)
+    }
+
+    /// When the train stopped for the last time, unless it got going again
+    /// afterwards.
+    pub fn arrived_at(&self) -> Option<DateTime<Utc>> {
+        self.final_arrival().map(|(time, _)| time)
+    }
+
+    fn final_arrival(&self) -> Option<(DateTime<Utc>, Option<&str>)> {
+        self.events.iter().rev().find_map(|event| match event {
+            TripEvent::Arrival { time, station } => Some(Some((*time, station.as_deref()))),
+            TripEvent::Departure { .. } => Some(None),
+            _ => None,
+        })?
+    }
+
+    /// How far the train went, from the speed samples.
+    pub fn distance_km(&self) -> f64 {
+        self.samples
+            .windows(2)
+            .filter_map(|pair| {
+                let hours = (pair[1].time - pair[0].time).num_milliseconds() as f64 / 3_600_000.0;
+                let gap = pair[1].time - pair[0].time > Duration::seconds(MAX_SAMPLE_GAP_SECONDS);
+                let (Some(a), Some(b)) = (pair[0].speed_kmh, pair[1].speed_kmh) else {
+                    return None;
+                };
+                (!gap).then_some((a + b) / 2.0 * hours)
+            })
+            .sum()
+    }
+
+    /// The stop called `name`, if the portal listed it.
+    pub fn stop(&self, name: &str) -> Option<&Stop> {
+        self.stops.iter().find(|stop| stop.name == name)
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.json", self.started_at.format("%Y%m%dT%H%M%SZ"))
+    }
+}
+
+/// Records journeys to `<data_dir>/journeys`, one JSON file each.
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    current: Mutex<Option<Journey>>,
+}
+
+impl Recorder {
+    /// Picks up the last journey if it ended recently, e.g. when the app was
+    /// restarted on the train.
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        let current = load_journeys(&dir)?.pop().filter(|journey| {
+            Utc::now() - journey.ended_at < Duration::minutes(JOURNEY_GAP_MINUTES)
+        });
+        Ok(Self {
+            dir,
+            current: Mutex::new(current),
+        })
+    }
+
+    /// The journey we're on, if any.
+    pub fn current(&self) -> Option<Journey> {
+        self.current.lock().clone()
+    }
+
+    /// Records until `shutdown` is cancelled, saving every `SAVE_INTERVAL` and
+    /// once more at the end.
+    pub async fn run(
+        self: Arc<Self>,
+        state: SharedState,
+        mut events: broadcast::Receiver<TripEvent>,
+        shutdown: CancellationToken,
+    ) {
+        let mut save = tokio::time::interval(SAVE_INTERVAL);
+        let mut dirty = false;
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = save.tick() => {
+                    if dirty {
+                        self.save();
+                        dirty = false;
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        let Some(status) = state.read().live().cloned() else {
+                            continue;
+                        };
+                        if let Some(finished) = self.observe(&event, &status, Utc::now()) {
+                            self.save_journey(&finished);
+                        }
+                        dirty = true;
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+        if dirty {
+            self.save();
+        }
+    }
+
+    /// Adds what `event` tells us to the current journey, starting a new one
+    /// if need be. Returns the previous journey when that happens.
+    fn observe(
+        &self,
+        event: &TripEvent,
+        status: &TripStatus,
+        now: DateTime<Utc>,
+    ) -> Option<Journey> {
+        let mut current = self.current.lock();
+        let same_journey = current.as_ref().is_some_and(|journey| {
+            journey.train() == status.train()
+                && now - journey.ended_at < Duration::minutes(JOURNEY_GAP_MINUTES)
+        });
+        let finished = if same_journey {
+            None
+        } else {
+            current.replace(Journey::new(status, now))
+        };
+        let journey = current.as_mut().expect("just made sure there is one");
+
+        journey.ended_at = now;
+        if !status.stops.is_empty() {
+            journey.stops = status.stops.clone();
+        }
+        if journey.destination.is_none() {
+            journey.destination = status.destination.clone();
+        }
+        match event {
+            TripEvent::Arrival { .. } | TripEvent::Departure { .. } | TripEvent::Delay { .. } => {
+                journey.events.push(event.clone())
+            }
+            TripEvent::Speed { time, .. } => {
+                let due = journey.samples.last().is_none_or(|last| {
+                    *time - last.time >= Duration::seconds(SAMPLE_INTERVAL_SECONDS)
+                });
+                if due {
+                    journey.samples.push(Sample::of(status, *time));
+                }
+            }
+            _ => {}
+        }
+        finished
+    }
+
+    fn save(&self) {
+        if let Some(journey) = self.current() {
+            self.save_journey(&journey);
+        }
+    }
+
+    fn save_journey(&self, journey: &Journey) {
+        let write = || -> io::Result<()> {
+            fs::create_dir_all(&self.dir)?;
+            let path = self.dir.join(journey.file_name());
+            // a crash halfway through must not lose what was there before
+            let temp = path.with_extension("tmp");
+            fs::write(&temp, serde_json::to_vec(journey)?)?;
+            fs::rename(temp, path)
+        };
+        if let Err(e) = write() {
+            eprintln!("recorder: can't save to {}: {e}", self.dir.display());
+        }
+    }
+}
+
+/// Every journey recorded in `dir`, oldest first.
+pub fn load_journeys(dir: &Path) -> io::Result<Vec<Journey>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut journeys = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            match serde_json::from_slice::<Journey>(&fs::read(&path)?) {
+                Ok(journey) => journeys.push(journey),
+                Err(e) => eprintln!("skipping {}: {e}", path.display()),
+            }
+        }
+    }
+    journeys.sort_by_key(|journey| journey.started_at);
+    Ok(journeys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(train_type: &str, trip_number: &str, speed_kmh: f64) -> TripStatus {
+        let stop = |name: &str| Stop {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        TripStatus {
+            speed_kmh: Some(speed_kmh),
+            train_type: Some(train_type.to_string()),
+            trip_number: Some(trip_number.to_string()),
+            destination: Some("Wien Hbf".to_string()),
+            next_stop: Some(stop("Linz Hbf")),
+            stops: vec![stop("Salzburg Hbf"), stop("Linz Hbf"), stop("Wien Hbf")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn splits_and_saves_journeys() {
+        let dir =
+            std::env::temp_dir().join(format!("traveltracker-journeys-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let recorder = Recorder::open(dir.clone()).unwrap();
+        let start = Utc::now() - Duration::hours(3);
+        let at = |minutes| start + Duration::minutes(minutes);
+
+        let rj61 = status("RJ", "61", 160.0);
+        let speed = |minutes| TripEvent::Speed {
+            time: at(minutes),
+            speed_kmh: 160.0,
+        };
+        assert!(recorder.observe(&speed(0), &rj61, at(0)).is_none());
+        recorder.observe(&speed(0), &rj61, at(0));
+        recorder.observe(
+            &TripEvent::Arrival {
+                time: at(25),
+                station: Some("Linz Hbf".to_string()),
+            },
+            &rj61,
+            at(25),
+        );
+        let journey = recorder.current().unwrap();
+        assert_eq!(journey.samples.len(), 1);
+        assert_eq!(journey.from(), Some("Salzburg Hbf"));
+        assert_eq!(journey.to(), Some("Linz Hbf"));
+        assert_eq!(journey.arrived_at(), Some(at(25)));
+
+        // changing trains at Linz
+        let finished = recorder
+            .observe(&speed(28), &status("REX", "1520", 5.0), at(28))
+            .unwrap();
+        assert_eq!(finished.train().as_deref(), Some("RJ 61"));
+        recorder.save_journey(&finished);
+        assert_eq!(
+            recorder.current().unwrap().boarded_at.as_deref(),
+            Some("Linz Hbf")
+        );
+        recorder.save();
+
+        let journeys = load_journeys(&dir).unwrap();
+        assert_eq!(journeys.len(), 2);
+        assert_eq!(journeys[0], finished);
+        assert_eq!(journeys[1].train().as_deref(), Some("REX 1520"));
+        // long over by now
+        assert!(Recorder::open(dir.clone()).unwrap().current().is_none());
+
+        let mut journey = journeys[0].clone();
+        journey.samples = [(0, 120.0), (30, 120.0), (60, 60.0), (600, 60.0)]
+            .map(|(seconds, speed_kmh)| Sample {
+                time: start + Duration::seconds(seconds),
+                raw_speed_kmh: Some(speed_kmh),
+                position: None,
+                speed_kmh: Some(speed_kmh),
+                next_stop: None,
+                delay_minutes: None,
+            })
+            .to_vec();
+        // 1 km, 0.75 km, and nothing across the gap
+        assert!((journey.distance_km() - 1.75).abs() < 1e-9);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{
+    cli::DateRange,
+    config::Config,
+    recorder::{load_journeys, Journey},
+};
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use std::{error::Error, fs, path::PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Csv,
+    Markdown,
+    /// A table that prints well, e.g. to PDF from a browser
+    Html,
+}
+
+const COLUMNS: [&str; 10] = [
+    "Date",
+    "Train",
+    "From",
+    "To",
+    "Departure",
+    "Arrival",
+    "Duration",
+    "Distance (km)",
+    "Departure delay (min)",
+    "Arrival delay (min)",
+];
+
+/// One journey, as it shows up in a report.
+#[derive(Debug, Clone, PartialEq)]
+struct Row {
+    departure: DateTime<Local>,
+    arrival: DateTime<Local>,
+    train: String,
+    from: String,
+    to: String,
+    distance_km: f64,
+    departure_delay: Option<i64>,
+    arrival_delay: Option<i64>,
+}
+
+impl Row {
+    fn new(journey: &Journey) -> Self {
+        let from = journey.from();
+        let to = journey.to();
+        let departure = journey.departed_at();
+        Self {
+            departure: departure.with_timezone(&Local),
+            arrival: journey
+                .arrived_at()
+                .unwrap_or(journey.ended_at)
+                .max(departure)
+                .with_timezone(&Local),
+            train: journey.train().unwrap_or_default(),
+            from: from.unwrap_or_default().to_string(),
+            to: to.unwrap_or_default().to_string(),
+            distance_km: journey.distance_km(),
+            departure_delay: from
+                .and_then(|from| journey.stop(from))
+                .and_then(|stop| stop.departure.delay_minutes()),
+            arrival_delay: to
+                .and_then(|to| journey.stop(to))
+                .and_then(|stop| stop.arrival.delay_minutes()),
+        }
+    }
+
+    fn minutes(&self) -> i64 {
+        (self.arrival - self.departure).num_minutes()
+    }
+
+    fn cells(&self) -> [String; 10] {
+        let delay = |minutes: Option<i64>| minutes.map(|m| m.to_string()).unwrap_or_default();
+        [
+            self.departure.format("%Y-%m-%d").to_string(),
+            self.train.clone(),
+            self.from.clone(),
+            self.to.clone(),
+            self.departure.format("%H:%M").to_string(),
+            self.arrival.format("%H:%M").to_string(),
+            duration(self.minutes()),
+            format!("{:.1}", self.distance_km),
+            delay(self.departure_delay),
+            delay(self.arrival_delay),
+        ]
+    }
+}
+
+/// `traveltracker report`: the recorded journeys in `range`, to `output` or
+/// stdout.
+pub fn export(
+    config: &Config,
+    range: &DateRange,
+    format: ReportFormat,
+    output: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let rows = load_journeys(&config.journeys_dir()?)?
+        .iter()
+        .filter(|journey| range.contains(journey.started_at))
+        .map(Row::new)
+        .collect::<Vec<_>>();
+    let report = match format {
+        ReportFormat::Csv => csv(&rows),
+        ReportFormat::Markdown => markdown(&rows),
+        ReportFormat::Html => html(&rows, range, Utc::now()),
+    };
+    match output {
+        Some(path) => fs::write(path, report)?,
+        None => print!("{report}"),
+    }
+    Ok(())
+}
+
+fn duration(minutes: i64) -> String {
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Journeys, total time and total distance.
+fn totals(rows: &[Row]) -> String {
+    let minutes = rows.iter().map(Row::minutes).sum();
+    let distance_km: f64 = rows.iter().map(|row| row.distance_km).sum();
+    format!(
+        "{} journeys, {} h, {distance_km:.1} km",
+        rows.len(),
+        duration(minutes)
+    )
+}
+
+fn csv(rows: &[Row]) -> String {
+    let quote = |cell: &str| {
+        if cell.contains([',', '"', '\n']) {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        } else {
+            cell.to_string()
+        }
+    };
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .map(|cell| quote(cell))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut out = line(&COLUMNS.map(str::to_string)) + "\n";
+    for row in rows {
+        out += &(line(&row.cells()) + "\n");
+    }
+    out
+}
+
+fn markdown(rows: &[Row]) -> String {
+    let line = |cells: &[String]| {
+        let cells = cells
+            .iter()
+            .map(|cell| cell.replace('|', "\\|"))
+            .collect::<Vec<_>>();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut out = line(&COLUMNS.map(str::to_string));
+    out += &line(&COLUMNS.map(|_| "---".to_string()));
+    for row in rows {
+        out += &line(&row.cells());
+    }
+    out + "\n" + &totals(rows) + "\n"
+}
+
+fn html(rows: &[Row], range: &DateRange, now: DateTime<Utc>) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    };
+    let cells = |tag: &str, cells: &[String]| {
+        cells
+            .iter()
+            .map(|cell| format!("<{tag}>{}</{tag}>", escape(cell)))
+            .collect::<String>()
+    };
+    let period = match (range.from, range.to) {
+        (None, None) => "All journeys".to_string(),
+        (from, to) => format!(
+            "{} – {}",
+            from.map(|d| d.to_string()).unwrap_or_default(),
+            to.map(|d| d.to_string()).unwrap_or_default()
+        ),
+    };
+
+    let mut body = String::new();
+    for row in rows {
+        body += &format!("<tr>{}</tr>\n", cells("td", &row.cells()));
+    }
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Travel report</title>
+<style>
+@page {{ size: A4 landscape; margin: 15mm; }}
+body {{ font-family: sans-serif; font-size: 10pt; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ border-bottom: 1px solid #ccc; padding: 4px 6px; text-align: left; }}
+tr {{ page-break-inside: avoid; }}
+</style>
+</head>
+<body>
+<h1>Travel report</h1>
+<p>{period} · generated {generated}</p>
+<table>
+<thead><tr>{head}</tr></thead>
+<tbody>
+{body}</tbody>
+</table>
+<p>{totals}</p>
+</body>
+</html>
+"#,
+        period = escape(&period),
+        generated = now.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        head = cells("th", &COLUMNS.map(str::to_string)),
+        totals = escape(&totals(rows)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn row(train: &str, from: &str, minutes: i64, arrival_delay: Option<i64>) -> Row {
+        let departure = Local.with_ymd_and_hms(2024, 3, 1, 13, 8, 0).unwrap();
+        Row {
+            departure,
+            arrival: departure + chrono::Duration::minutes(minutes),
+            train: train.to_string(),
+            from: from.to_string(),
+            to: "Wien Hbf".to_string(),
+            distance_km: 312.04,
+            departure_delay: Some(0),
+            arrival_delay,
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let rows = [
+            row("RJ 61", "Salzburg Hbf", 142, Some(3)),
+            row("WB 917", "Linz, Hbf", 75, None),
+        ];
+
+        let csv = csv(&rows);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            "2024-03-01,RJ 61,Salzburg Hbf,Wien Hbf,13:08,15:30,2:22,312.0,0,3"
+        );
+        assert_eq!(
+            lines[2],
+            "2024-03-01,WB 917,\"Linz, Hbf\",Wien Hbf,13:08,14:23,1:15,312.0,0,"
+        );
+
+        let markdown = markdown(&rows);
+        assert!(markdown
+            .contains("| 2024-03-01 | RJ 61 | Salzburg Hbf | Wien Hbf | 13:08 | 15:30 | 2:22 |"));
+        assert!(markdown.ends_with("2 journeys, 3:37 h, 624.1 km\n"));
+
+        let range = DateRange {
+            from: NaiveDate::from_ymd_opt(2024, 3, 1),
+            to: None,
+        };
+        let html = html(&rows, &range, Utc::now());
+        assert!(html.contains("<td>Linz, Hbf</td>"));
+        assert!(html.contains("2024-03-01 – "));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    api,
+    command::AppEvent,
+    config::Config,
+    eta::EtaEstimator,
+    mqtt,
+    poller::{Poller, PollerControl},
+    providers,
+    punctuality::{self, Punctuality},
+    recorder::{load_journeys, Recorder},
+    state::SharedState,
+    status_line, webhooks,
+};
+use std::{collections::BTreeMap, error::Error, io, sync::mpsc::Sender, sync::Arc};
+use tokio::{sync::broadcast, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+/// The poller and everything it feeds, whichever front-end shows the trip.
+/// Only the menu has a use for some of it.
+pub struct Services {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub client: reqwest::Client,
+    pub state: SharedState,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub control: Arc<PollerControl>,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub recorder: Arc<Recorder>,
+    /// Per train, from the journeys recorded before this run.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub punctuality: BTreeMap<String, Punctuality>,
+    pub tasks: Tasks,
+}
+
+/// Background tasks that all stop once `shutdown` is cancelled.
+pub struct Tasks {
+    poller: JoinHandle<()>,
+    recording: JoinHandle<()>,
+    status_cache: JoinHandle<()>,
+    api: Option<JoinHandle<io::Result<()>>>,
+    mqtt: Option<JoinHandle<()>>,
+    webhooks: Option<JoinHandle<()>>,
+}
+
+impl Services {
+    /// Starts everything `config` asks for. The poller reports to `sender`.
+    pub async fn start(
+        config: &Config,
+        sender: Sender<AppEvent>,
+        shutdown: CancellationToken,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::new();
+        let state = SharedState::default();
+        let control = Arc::new(PollerControl::default());
+        let metrics = Arc::default();
+        let (events, _) = broadcast::channel(64);
+
+        let api = match config.api {
+            Some(api) => {
+                let listener = tokio::net::TcpListener::bind(api.listen)
+                    .await
+                    .map_err(|e| format!("can't listen on {}: {e}", api.listen))?;
+                Some(tokio::spawn(api::serve(
+                    listener,
+                    state.clone(),
+                    Arc::clone(&metrics),
+                    events.clone(),
+                    shutdown.clone(),
+                )))
+            }
+            None => None,
+        };
+
+        let mqtt = config.mqtt.clone().map(|mqtt| {
+            tokio::spawn(mqtt::publish(
+                mqtt,
+                state.clone(),
+                events.subscribe(),
+                shutdown.clone(),
+            ))
+        });
+
+        let webhooks = if config.webhooks.is_empty() {
+            None
+        } else {
+            let outbox = webhooks::Outbox::open(config.data_dir()?.join("webhook-outbox.json"))?;
+            Some(tokio::spawn(webhooks::run(
+                config.webhooks.clone(),
+                outbox,
+                state.clone(),
+                events.subscribe(),
+                shutdown.clone(),
+            )))
+        };
+
+        let status_cache = tokio::spawn(status_line::cache(
+            config.status_cache_path()?,
+            state.clone(),
+            events.subscribe(),
+            shutdown.clone(),
+        ));
+
+        let journeys = load_journeys(&config.journeys_dir()?)?;
+        let recorder = Arc::new(Recorder::open(config.journeys_dir()?)?);
+        let recording = tokio::spawn(recorder.clone().run(
+            state.clone(),
+            events.subscribe(),
+            shutdown.clone(),
+        ));
+
+        let poller = Poller {
+            client: client.clone(),
+            control: control.clone(),
+            state: state.clone(),
+            metrics,
+            providers: providers::all(config),
+            smoothing: config.speed_smoothing,
+            eta: EtaEstimator::new(&journeys, config.data_dir()?.join("eta-accuracy.json")),
+        };
+        let poller = tokio::spawn(poller.run(sender, events, shutdown));
+
+        Ok(Self {
+            client,
+            state,
+            control,
+            recorder,
+            punctuality: punctuality::by_train(&journeys),
+            tasks: Tasks {
+                poller,
+                recording,
+                status_cache,
+                api,
+                mqtt,
+                webhooks,
+            },
+        })
+    }
+}
+
+impl Tasks {
+    /// Waits for everything to stop, the poller first so that nothing new
+    /// comes in while the rest closes files and connections.
+    pub async fn finish(self) -> Result<(), Box<dyn Error>> {
+        self.poller.await?;
+        self.recording.await?;
+        self.status_cache.await?;
+        if let Some(api) = self.api {
+            api.await??;
+        }
+        if let Some(mqtt) = self.mqtt {
+            mqtt.await?;
+        }
+        if let Some(webhooks) = self.webhooks {
+            webhooks.await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+
+/// Cancels `shutdown` on SIGINT or SIGTERM. Returns early if something else
+/// cancels it first.
+pub async fn cancel_on_signal(shutdown: CancellationToken) {
+    let mut sigterm = signal(SignalKind::terminate()).expect("install SIGTERM handler");
+    let mut sigint = signal(SignalKind::interrupt()).expect("install SIGINT handler");
+
+    tokio::select! {
+        _ = shutdown.cancelled() => return,
+        _ = sigterm.recv() => eprintln!("got SIGTERM, shutting down"),
+        _ = sigint.recv() => eprintln!("got SIGINT, shutting down"),
+    }
+    shutdown.cancel();
+}
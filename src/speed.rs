@@ -0,0 +1,197 @@
+use crate::trip::{TripStatus, MAX_PLAUSIBLE_KMH};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+/// More than trains can speed up or brake (km/h per second), with room for
+/// portals that round or lag.
+const MAX_CHANGE_KMH_PER_SECOND: f64 = 10.0;
+/// A jump that's still there after this many polls is real, e.g. after the
+/// portal was gone for a while.
+const REJECT_AT_MOST: u32 = 3;
+const MOVING_AVERAGE_OVER: usize = 5;
+/// How much the speed may change between polls, and how far off the portal
+/// may be, for the Kalman filter ((km/h)² per second and (km/h)²).
+const KALMAN_PROCESS_NOISE: f64 = 4.0;
+const KALMAN_MEASUREMENT_NOISE: f64 = 25.0;
+
+/// How the speed in the title and everywhere else is smoothed. What the
+/// portal said goes into `raw_speed_kmh` either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Smoothing {
+    #[default]
+    Off,
+    /// Of the last few speeds.
+    MovingAverage,
+    /// Follows changes faster than the moving average, and jitter less.
+    Kalman,
+}
+
+/// Drops speeds that can't be right, then smooths what's left.
+#[derive(Debug, Default)]
+pub struct SpeedFilter {
+    smoothing: Smoothing,
+    /// The last speed that passed `check`.
+    last: Option<(DateTime<Utc>, f64)>,
+    rejected: u32,
+    recent: VecDeque<f64>,
+    /// Estimate and its variance.
+    kalman: Option<(DateTime<Utc>, f64, f64)>,
+}
+
+impl SpeedFilter {
+    pub fn new(smoothing: Smoothing) -> Self {
+        Self {
+            smoothing,
+            ..Default::default()
+        }
+    }
+
+    /// Keeps what the portal said in `raw_speed_kmh`, then takes out speeds
+    /// that aren't a speed (negative, infinite, or faster than any train) or
+    /// that jumped further than a train can accelerate since the last one.
+    /// Goes first, before GPS gets to fill in the speed.
+    pub fn check(&mut self, status: &mut TripStatus, time: DateTime<Utc>) {
+        status.raw_speed_kmh = status.speed_kmh;
+        let Some(kmh) = status.speed_kmh else {
+            return;
+        };
+        if !(0.0..=MAX_PLAUSIBLE_KMH).contains(&kmh) {
+            status.speed_kmh = None;
+            return;
+        }
+        if let Some((last_time, last_kmh)) = self.last {
+            let seconds = ((time - last_time).num_milliseconds() as f64 / 1000.0).max(1.0);
+            let jump = (kmh - last_kmh).abs() > MAX_CHANGE_KMH_PER_SECOND * seconds;
+            if jump && self.rejected < REJECT_AT_MOST {
+                self.rejected += 1;
+                status.speed_kmh = None;
+                return;
+            }
+        }
+        self.rejected = 0;
+        self.last = Some((time, kmh));
+    }
+
+    /// Smooths `speed_kmh`, whether it's the portal's or from GPS.
+    pub fn smooth(&mut self, status: &mut TripStatus, time: DateTime<Utc>) {
+        let Some(kmh) = status.speed_kmh else {
+            return;
+        };
+        status.speed_kmh = Some(match self.smoothing {
+            Smoothing::Off => kmh,
+            Smoothing::MovingAverage => {
+                if self.recent.len() == MOVING_AVERAGE_OVER {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back(kmh);
+                self.recent.iter().sum::<f64>() / self.recent.len() as f64
+            }
+            Smoothing::Kalman => {
+                let (estimate, variance) = match self.kalman {
+                    None => (kmh, KALMAN_MEASUREMENT_NOISE),
+                    Some((last_time, estimate, variance)) => {
+                        let seconds = (time - last_time).num_milliseconds() as f64 / 1000.0;
+                        let variance = variance + KALMAN_PROCESS_NOISE * seconds.max(0.0);
+                        let gain = variance / (variance + KALMAN_MEASUREMENT_NOISE);
+                        (estimate + gain * (kmh - estimate), (1.0 - gain) * variance)
+                    }
+                };
+                self.kalman = Some((time, estimate, variance));
+                estimate
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn run(filter: &mut SpeedFilter, speeds: &[Option<f64>]) -> Vec<(Option<f64>, Option<f64>)> {
+        let start = Utc::now();
+        speeds
+            .iter()
+            .enumerate()
+            .map(|(second, speed_kmh)| {
+                let time = start + Duration::seconds(second as i64);
+                let mut status = TripStatus {
+                    speed_kmh: *speed_kmh,
+                    ..Default::default()
+                };
+                filter.check(&mut status, time);
+                filter.smooth(&mut status, time);
+                (status.raw_speed_kmh, status.speed_kmh)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn garbage_and_spikes() {
+        let mut filter = SpeedFilter::default();
+        let speeds = run(
+            &mut filter,
+            &[
+                Some(180.0),
+                Some(-3.0),
+                Some(f64::NAN),
+                Some(f64::INFINITY),
+                Some(181.0),
+                Some(0.0),
+                Some(182.0),
+            ],
+        );
+        let kept = speeds.iter().map(|(_, kmh)| *kmh).collect::<Vec<_>>();
+        assert_eq!(
+            kept,
+            [
+                Some(180.0),
+                None,
+                None,
+                None,
+                Some(181.0),
+                None,
+                Some(182.0)
+            ]
+        );
+        // what the portal said, nonsense or not
+        assert_eq!(speeds[1].0, Some(-3.0));
+        assert_eq!(speeds[5].0, Some(0.0));
+
+        // the portal really did come back with something else
+        let mut filter = SpeedFilter::default();
+        let speeds = run(
+            &mut filter,
+            &[
+                Some(0.0),
+                Some(120.0),
+                Some(121.0),
+                Some(122.0),
+                Some(123.0),
+            ],
+        );
+        assert_eq!(speeds[3].1, None);
+        assert_eq!(speeds[4].1, Some(123.0));
+    }
+
+    #[test]
+    fn smoothing() {
+        let jittery = [100.0, 110.0, 100.0, 110.0, 100.0, 110.0, 100.0, 110.0].map(Some);
+
+        let speeds = run(&mut SpeedFilter::new(Smoothing::MovingAverage), &jittery);
+        let (raw, smoothed) = speeds[7];
+        assert_eq!(raw, Some(110.0));
+        assert_eq!(smoothed, Some(106.0));
+
+        let speeds = run(&mut SpeedFilter::new(Smoothing::Kalman), &jittery);
+        for (raw, smoothed) in &speeds[3..] {
+            let (raw, smoothed) = (raw.unwrap(), smoothed.unwrap());
+            assert!((smoothed - 105.0).abs() < (raw - 105.0).abs());
+        }
+
+        let speeds = run(&mut SpeedFilter::default(), &jittery);
+        assert!(speeds.iter().all(|(raw, smoothed)| raw == smoothed));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{
+    eta::Accuracy,
+    providers::Provider,
+    trip::{Position, TripStatus},
+};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, sync::Arc};
+
+/// An hour's worth at the default poll interval.
+const HISTORY_LEN: usize = 3600;
+
+pub type SharedState = Arc<RwLock<TripState>>;
+
+/// Everything the poller has found out, shared with whoever wants to show it.
+#[derive(Debug, Default)]
+pub struct TripState {
+    /// The last status we got, even if the portal has since gone away.
+    pub status: Option<TripStatus>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Whether the last poll reached the portal.
+    pub connected: bool,
+    /// The portal `status` came from.
+    pub provider: Option<Arc<dyn Provider>>,
+    pub eta_accuracy: Accuracy,
+    pub history: VecDeque<Sample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub time: DateTime<Utc>,
+    pub speed_kmh: Option<f64>,
+    /// What the portal said, missing from recordings made before smoothing.
+    #[serde(default)]
+    pub raw_speed_kmh: Option<f64>,
+    #[serde(default)]
+    pub position: Option<Position>,
+    pub next_stop: Option<String>,
+    pub delay_minutes: Option<i64>,
+}
+
+impl Sample {
+    pub fn of(status: &TripStatus, time: DateTime<Utc>) -> Self {
+        Self {
+            time,
+            speed_kmh: status.speed_kmh,
+            raw_speed_kmh: status.raw_speed_kmh,
+            position: status.position,
+            next_stop: status.next_stop.as_ref().map(|s| s.name.clone()),
+            delay_minutes: status.delay_minutes(),
+        }
+    }
+}
+
+impl TripState {
+    pub fn update(&mut self, status: TripStatus, now: DateTime<Utc>) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(Sample::of(&status, now));
+        self.status = Some(status);
+        self.updated_at = Some(now);
+        self.connected = true;
+    }
+
+    pub fn disconnect(&mut self) {
+        self.connected = false;
+    }
+
+    /// The current status, but only while we're actually connected.
+    pub fn live(&self) -> Option<&TripStatus> {
+        self.status.as_ref().filter(|_| self.connected)
+    }
+}
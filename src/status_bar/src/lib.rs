@@ -196,11 +196,34 @@ impl STBMenuItemCallback {
     }
 }
 
+/// What a menu item callback gets told about the item that was clicked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuItemContext {
+    pub tag: isize,
+    pub title: String,
+}
+
+impl MenuItemContext {
+    unsafe fn from_sender(sender: *mut NSMenuItem) -> Self {
+        match sender.as_ref() {
+            Some(sender) => Self {
+                tag: sender.tag(),
+                title: sender.title().to_string(),
+            },
+            None => Self {
+                tag: 0,
+                title: String::new(),
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct MenuItem {
     inner: Id<NSMenuItem>,
 
     title: String,
+    tag: isize,
     callback: Option<MenuItemCallback>,
     submenu: Option<Menu>,
 }
@@ -210,6 +233,20 @@ impl MenuItem {
         title: impl AsRef<str>,
         callback: Option<Box<dyn Fn() + 'static>>,
         submenu: Option<Menu>,
+    ) -> Self {
+        let callback = callback.map(|callback| -> Box<dyn Fn(MenuItemContext)> {
+            Box::new(move |_| callback())
+        });
+        Self::new_with_tag(title, 0, callback, submenu)
+    }
+
+    /// Like `new`, but the item carries a `tag` that is handed back to the
+    /// callback, so one callback can serve many items.
+    pub fn new_with_tag(
+        title: impl AsRef<str>,
+        tag: isize,
+        callback: Option<Box<dyn Fn(MenuItemContext) + 'static>>,
+        submenu: Option<Menu>,
     ) -> Self {
         let title = title.as_ref();
         unsafe {
@@ -219,6 +256,7 @@ impl MenuItem {
                 None,
                 &NSString::from_str(""),
             );
+            inner.setTag(tag);
 
             let callback = callback.map(|callback| {
                 let callback = MenuItemCallback::new(callback);
@@ -236,6 +274,7 @@ impl MenuItem {
             Self {
                 inner,
                 title,
+                tag,
                 callback,
                 submenu,
             }
@@ -249,6 +288,10 @@ impl MenuItem {
     pub fn title(&self) -> &str {
         &self.title
     }
+
+    pub fn tag(&self) -> isize {
+        self.tag
+    }
 }
 
 impl Drop for MenuItem {
@@ -269,9 +312,9 @@ struct MenuItemCallback {
 }
 
 impl MenuItemCallback {
-    fn new(callback: Box<dyn Fn() + 'static>) -> Self {
-        let callback_block = ConcreteBlock::new(move |_: *mut NSMenuItem| {
-            callback();
+    fn new(callback: Box<dyn Fn(MenuItemContext) + 'static>) -> Self {
+        let callback_block = ConcreteBlock::new(move |sender: *mut NSMenuItem| {
+            callback(unsafe { MenuItemContext::from_sender(sender) });
         })
         .copy();
         let inner = STBMenuItemCallback::new(&*callback_block);
@@ -575,6 +618,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn click_menu_with_tag() {
+        unsafe {
+            let clicked = Rc::new(RefCell::new(vec![]));
+            let status_item = {
+                let clicked = clicked.clone();
+                let callback = Rc::new(move |context: MenuItemContext| {
+                    clicked.borrow_mut().push(context);
+                });
+                let item = |title: &str, tag: isize| {
+                    let callback = callback.clone();
+                    MenuItem::new_with_tag(
+                        title,
+                        tag,
+                        Some(Box::new(move |context| callback(context))),
+                        None,
+                    )
+                };
+                StatusItem::new_impl(
+                    NSStatusItem::new(),
+                    "000",
+                    Menu::new(vec![item("001", 1), item("002", 2)]),
+                )
+            };
+
+            assert_eq!(status_item.menu().items()[0].tag(), 1);
+            assert_eq!(status_item.menu().items()[1].tag(), 2);
+
+            let menu = status_item.inner.menu().unwrap();
+            for index in [1, 0] {
+                let menu_item_inner = menu.itemAtIndex(index).unwrap();
+                let _: () =
+                    msg_send![&menu_item_inner.target().unwrap(), call:menu_item_inner.as_ref()];
+            }
+
+            assert_eq!(
+                *clicked.borrow(),
+                vec![
+                    MenuItemContext {
+                        tag: 2,
+                        title: "002".to_string(),
+                    },
+                    MenuItemContext {
+                        tag: 1,
+                        title: "001".to_string(),
+                    },
+                ]
+            );
+        }
+    }
+
     #[derive(Default)]
     pub struct EventLoopTestCounter {
         called_finish_launching: u32,
@@ -0,0 +1,282 @@
+use crate::{
+    api::StatusView, config::Config, events::TripEvent, state::SharedState, trip::TripStatus,
+    units::SpeedUnit,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+pub const DEFAULT_FORMAT: &str = "{train} → {destination} · {speed} · {delay}";
+const PLACEHOLDERS: [&str; 7] = [
+    "train",
+    "destination",
+    "speed",
+    "next",
+    "eta",
+    "delay",
+    "delay_minutes",
+];
+/// A status older than this is from an instance that isn't running anymore.
+const STALE_AFTER_SECONDS: i64 = 30;
+const API_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// What `/status` answers, which is also what's cached.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Snapshot {
+    speed_kmh: Option<f64>,
+    train: Option<String>,
+    destination: Option<String>,
+    next_stop: Option<NextStop>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct NextStop {
+    name: String,
+    eta: Option<String>,
+    delay_minutes: Option<i64>,
+}
+
+/// Keeps `/status` in a file for `traveltracker status` to read when the API
+/// isn't enabled, and removes it while there's no portal and on shutdown.
+pub async fn cache(
+    path: PathBuf,
+    state: SharedState,
+    mut events: broadcast::Receiver<TripEvent>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            event = events.recv() => match event {
+                Ok(_) | Err(RecvError::Lagged(_)) => {
+                    let json = {
+                        let state = state.read();
+                        state
+                            .live()
+                            .map(|status| StatusView::new(status, state.updated_at))
+                            .map(|view| serde_json::to_vec(&view).expect("status serializes"))
+                    };
+                    let result = match json {
+                        Some(json) => write(&path, &json),
+                        None => remove(&path),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("can't update {}: {e}", path.display());
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+    let _ = remove(&path);
+}
+
+fn write(path: &Path, json: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // readers must never see half a file
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, json)?;
+    fs::rename(temp, path)
+}
+
+fn remove(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// `traveltracker status`: prints `format` filled in from the running
+/// instance, asking its API if there is one and reading the cached status
+/// otherwise. Returns whether we're on a train.
+pub async fn print(config: &Config, format: &str) -> Result<bool, Box<dyn Error>> {
+    check(format)?;
+    let snapshot = match from_api(config).await {
+        Some(snapshot) => snapshot,
+        None => from_cache(&config.status_cache_path()?, Utc::now())?,
+    };
+    match snapshot {
+        Some(snapshot) => {
+            println!("{}", render(format, &snapshot, config.unit));
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// `None` if the API isn't enabled or not answering, `Some(None)` if it says
+/// we're not on a train.
+async fn from_api(config: &Config) -> Option<Option<Snapshot>> {
+    let api = config.api?;
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/status", api.listen))
+        .timeout(API_TIMEOUT)
+        .send()
+        .await
+        .ok()?;
+    if response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+        return Some(None);
+    }
+    response
+        .error_for_status()
+        .ok()?
+        .json()
+        .await
+        .ok()
+        .map(Some)
+}
+
+fn from_cache(path: &Path, now: DateTime<Utc>) -> Result<Option<Snapshot>, Box<dyn Error>> {
+    let json = match fs::read(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("can't read {}: {e}", path.display()).into()),
+    };
+    let snapshot = serde_json::from_slice::<Snapshot>(&json)?;
+    let fresh = snapshot
+        .updated_at
+        .is_some_and(|updated_at| now - updated_at < Duration::seconds(STALE_AFTER_SECONDS));
+    Ok(Some(snapshot).filter(|_| fresh))
+}
+
+/// Fails on placeholders we don't know, before the user wonders why their
+/// prompt shows `{sped}`.
+pub fn check(format: &str) -> Result<(), String> {
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed `{{` in {format:?}"))?;
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder `{{{name}}}`, there are {}",
+                PLACEHOLDERS.map(|p| format!("{{{p}}}")).join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// `format` filled in from a status of our own rather than one from the API.
+pub fn line(format: &str, status: &TripStatus, unit: SpeedUnit) -> String {
+    let snapshot = Snapshot {
+        speed_kmh: status.speed_kmh,
+        train: status.train(),
+        destination: status.destination.clone(),
+        next_stop: status.next_stop.as_ref().map(|stop| NextStop {
+            name: stop.name.clone(),
+            eta: stop.arrival.best().map(str::to_string),
+            delay_minutes: stop.arrival.delay_minutes(),
+        }),
+        updated_at: None,
+    };
+    render(format, &snapshot, unit)
+}
+
+/// Fills in `format`. Parts between ` · ` that come out empty are left out,
+/// so that e.g. no delay doesn't leave a dangling separator.
+fn render(format: &str, snapshot: &Snapshot, unit: SpeedUnit) -> String {
+    let next = snapshot.next_stop.as_ref();
+    let delay_minutes = next.and_then(|n| n.delay_minutes);
+    let values = [
+        ("train", snapshot.train.clone()),
+        ("destination", snapshot.destination.clone()),
+        ("speed", snapshot.speed_kmh.map(|kmh| unit.format(kmh))),
+        ("next", next.map(|n| n.name.clone())),
+        ("eta", next.and_then(|n| n.eta.clone())),
+        (
+            "delay",
+            delay_minutes.filter(|m| *m != 0).map(|m| format!("{m:+}'")),
+        ),
+        ("delay_minutes", delay_minutes.map(|m| m.to_string())),
+    ];
+    let line = values
+        .iter()
+        .fold(format.to_string(), |line, (name, value)| {
+            line.replace(&format!("{{{name}}}"), value.as_deref().unwrap_or(""))
+        });
+    line.split(" · ")
+        .filter(|part| !part.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trip::{Stop, Times};
+
+    fn rj61() -> TripStatus {
+        TripStatus {
+            speed_kmh: Some(187.4),
+            train_type: Some("RJ".to_string()),
+            trip_number: Some("61".to_string()),
+            destination: Some("Wien Hbf".to_string()),
+            next_stop: Some(Stop {
+                name: "Linz Hbf".to_string(),
+                arrival: Times {
+                    scheduled: Some("14:05".to_string()),
+                    forecast: Some("14:09".to_string()),
+                },
+                departure: Times::default(),
+                ..Default::default()
+            }),
+            stops: vec![],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_templates() {
+        let now = Utc::now();
+        let json = serde_json::to_string(&StatusView::new(&rj61(), Some(now))).unwrap();
+        let mut snapshot = serde_json::from_str::<Snapshot>(&json).unwrap();
+
+        assert_eq!(
+            render(DEFAULT_FORMAT, &snapshot, SpeedUnit::Kmh),
+            "RJ 61 → Wien Hbf · 187 km/h · +4'"
+        );
+        assert_eq!(
+            render("{next} {eta} ({delay_minutes})", &snapshot, SpeedUnit::Mph),
+            "Linz Hbf 14:09 (4)"
+        );
+        snapshot.next_stop = None;
+        assert_eq!(
+            render(DEFAULT_FORMAT, &snapshot, SpeedUnit::Kmh),
+            "RJ 61 → Wien Hbf · 187 km/h"
+        );
+
+        assert!(check(DEFAULT_FORMAT).is_ok());
+        assert!(check("{sped}").is_err());
+        assert!(check("{train").is_err());
+    }
+
+    #[test]
+    fn stale_cache() {
+        let path =
+            std::env::temp_dir().join(format!("traveltracker-status-{}.json", std::process::id()));
+        let now = Utc::now();
+        assert_eq!(from_cache(&path, now).unwrap(), None);
+
+        write(
+            &path,
+            &serde_json::to_vec(&StatusView::new(&rj61(), Some(now))).unwrap(),
+        )
+        .unwrap();
+        assert!(from_cache(&path, now).unwrap().is_some());
+        assert_eq!(from_cache(&path, now + Duration::minutes(5)).unwrap(), None);
+        remove(&path).unwrap();
+    }
+}
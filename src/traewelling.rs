@@ -0,0 +1,457 @@
+use crate::trip::{local_time_near, parse_time, Stop, TripStatus};
+use chrono::{Duration, Local};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+const KEYRING_SERVICE: &str = "traveltracker";
+const KEYRING_USER: &str = "traewelling";
+
+/// The `[traewelling]` table of the config. The "Check in" menu only shows up
+/// when it's there.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraewellingConfig {
+    pub base_url: String,
+}
+
+impl Default for TraewellingConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://traewelling.de/api/v1".to_string(),
+        }
+    }
+}
+
+/// Asks for a token on stdin and keeps it in the system keychain.
+pub fn login() -> std::result::Result<(), Box<dyn Error>> {
+    eprint!("Träwelling token (Settings → API tokens on traewelling.de): ");
+    io::stderr().flush()?;
+    let mut token = String::new();
+    io::stdin().lock().read_line(&mut token)?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err("no token given".into());
+    }
+    keyring_entry()?.set_password(token)?;
+    eprintln!("Saved the token in the keychain.");
+    Ok(())
+}
+
+pub fn logout() -> std::result::Result<(), Box<dyn Error>> {
+    match keyring_entry()?.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn keyring_entry() -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+}
+
+/// Talks to the Träwelling API.
+pub struct Traewelling {
+    client: reqwest::Client,
+    config: TraewellingConfig,
+    /// Read from the keychain on every check-in when `None`, so that logging
+    /// in doesn't need a restart.
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Data<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Station {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Departure {
+    trip_id: String,
+    planned_when: Option<String>,
+    line: Line,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Line {
+    name: Option<String>,
+    fahrt_nr: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Trip {
+    stopovers: Vec<Stopover>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Stopover {
+    id: u64,
+    name: String,
+    arrival_planned: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CheckIn {
+    status: CheckedIn,
+}
+
+#[derive(Deserialize)]
+struct CheckedIn {
+    id: u64,
+}
+
+impl Traewelling {
+    #[cfg(target_os = "macos")]
+    pub fn new(client: reqwest::Client, config: TraewellingConfig) -> Self {
+        Self {
+            client,
+            config,
+            token: None,
+        }
+    }
+
+    /// Checks in to the trip `status` is about, from `origin` to
+    /// `destination`, and returns the id of the new Träwelling status.
+    ///
+    /// Träwelling only knows trips by their HAFAS id, so this looks up the
+    /// departures at `origin` around its scheduled time and picks the one with
+    /// our trip number.
+    pub async fn check_in(
+        &self,
+        status: &TripStatus,
+        origin: &Stop,
+        destination: &Stop,
+    ) -> Result<u64> {
+        let token = match &self.token {
+            Some(token) => token.clone(),
+            None => match keyring_entry().and_then(|entry| entry.get_password()) {
+                Ok(token) => token,
+                Err(keyring::Error::NoEntry) => {
+                    return Err("not logged in, run `traveltracker traewelling login`".into())
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+        let train = status
+            .train()
+            .ok_or("the portal doesn't say which train this is")?;
+
+        let stations: Vec<Station> = self
+            .get(
+                &token,
+                &["trains", "station", "autocomplete", &origin.name],
+                &[],
+            )
+            .await?;
+        let start = stations
+            .first()
+            .ok_or_else(|| format!("Träwelling doesn't know {}", origin.name))?;
+
+        let scheduled = origin
+            .departure
+            .scheduled
+            .as_deref()
+            .or(origin.arrival.scheduled.as_deref())
+            .and_then(parse_time)
+            .ok_or_else(|| format!("no departure time for {}", origin.name))?;
+        // a little early, departures are listed from `when` on
+        let when = local_time_near(scheduled, Local::now()) - Duration::minutes(5);
+        let departures: Vec<Departure> = self
+            .get(
+                &token,
+                &["station", &start.id.to_string(), "departures"],
+                &[("when", when.to_rfc3339())],
+            )
+            .await?;
+        let departure = departures
+            .into_iter()
+            .find(|d| is_train(&d.line, status, &train))
+            .ok_or_else(|| format!("{train} isn't among the departures at {}", origin.name))?;
+        let line_name = departure.line.name.unwrap_or(train);
+
+        let trip: Trip = self
+            .get(
+                &token,
+                &["trains", "trip"],
+                &[
+                    ("hafasTripId", departure.trip_id.clone()),
+                    ("lineName", line_name.clone()),
+                    ("start", start.id.to_string()),
+                ],
+            )
+            .await?;
+        let stop = find_stopover(&trip.stopovers, &destination.name)
+            .ok_or_else(|| format!("{line_name} doesn't stop at {}", destination.name))?;
+
+        let response = self
+            .client
+            .post(format!("{}/trains/checkin", self.config.base_url))
+            .bearer_auth(&token)
+            .json(&json!({
+                "tripId": departure.trip_id,
+                "lineName": line_name,
+                "start": start.id,
+                "destination": stop.id,
+                "departure": departure.planned_when,
+                "arrival": stop.arrival_planned,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Data<CheckIn>>()
+            .await?;
+        Ok(response.data.status.id)
+    }
+
+    /// `path` is percent-encoded segment by segment, as station names can
+    /// have slashes and question marks in them.
+    async fn get<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        path: &[&str],
+        query: &[(&str, String)],
+    ) -> Result<T> {
+        let mut url = reqwest::Url::parse(&self.config.base_url)?;
+        url.path_segments_mut()
+            .map_err(|()| format!("invalid Träwelling URL {}", self.config.base_url))?
+            .pop_if_empty()
+            .extend(path);
+        let response = self
+            .client
+            .get(url)
+            .bearer_auth(token)
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Data<T>>()
+            .await?;
+        Ok(response.data)
+    }
+}
+
+fn is_train(line: &Line, status: &TripStatus, train: &str) -> bool {
+    let squash = |s: &str| s.replace(' ', "").to_lowercase();
+    line.fahrt_nr.is_some() && line.fahrt_nr == status.trip_number
+        || line.name.as_deref().map(squash) == Some(squash(train))
+}
+
+/// The stopover called `name`. The portal and HAFAS don't always agree on
+/// names, e.g. `"Wien Hbf"` and `"Wien Hbf (U)"`, so after exact matches this
+/// tries names without a suffix in parentheses, and then a name starting with
+/// the other, but only if just one stopover does: `"Wien"` could be any of
+/// `"Wien Hbf"` and `"Wien Meidling"`.
+fn find_stopover<'a>(stopovers: &'a [Stopover], name: &str) -> Option<&'a Stopover> {
+    let exact = |s: &&Stopover| s.name.trim().to_lowercase() == name.trim().to_lowercase();
+    let name = without_suffix(name);
+    let bare = |s: &&Stopover| without_suffix(&s.name) == name;
+    let prefixed = stopovers
+        .iter()
+        .filter(|s| {
+            let other = without_suffix(&s.name);
+            other.starts_with(&name) || name.starts_with(&other)
+        })
+        .collect::<Vec<_>>();
+    stopovers
+        .iter()
+        .find(exact)
+        .or_else(|| stopovers.iter().find(bare))
+        .or_else(|| match prefixed[..] {
+            [only] => Some(only),
+            _ => None,
+        })
+}
+
+/// Lowercase and without a trailing `" (U)"` or the like.
+fn without_suffix(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    match name.rfind(" (") {
+        Some(index) if name.ends_with(')') => name[..index].to_string(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trip::Times;
+    use axum::{
+        extract::{Path, Query},
+        http::HeaderMap,
+        routing::{get, post},
+        Json, Router,
+    };
+    use parking_lot::Mutex;
+    use serde_json::Value;
+    use std::{collections::HashMap, net::Ipv4Addr, sync::Arc};
+    use tokio::net::TcpListener;
+
+    fn stop(name: &str, arrival: Option<&str>, departure: Option<&str>) -> Stop {
+        Stop {
+            name: name.to_string(),
+            arrival: Times {
+                scheduled: arrival.map(str::to_string),
+                forecast: None,
+            },
+            departure: Times {
+                scheduled: departure.map(str::to_string),
+                forecast: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn rj61() -> TripStatus {
+        let stops = vec![
+            stop("Salzburg Hbf", None, Some("13:08")),
+            stop("Linz Hbf", Some("14:05"), Some("14:07")),
+            stop("Wien Hbf", Some("15:30"), None),
+        ];
+        TripStatus {
+            train_type: Some("RJ".to_string()),
+            trip_number: Some("61".to_string()),
+            next_stop: Some(stops[1].clone()),
+            stops,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_stopovers_by_name() {
+        let stopovers = [
+            "Linz/Donau Ebelsberg",
+            "Linz Hbf",
+            "Wien Meidling",
+            "Wien Hbf (U)",
+            "Frankfurt (Main) Hbf",
+        ]
+        .map(|name| Stopover {
+            id: 0,
+            name: name.to_string(),
+            arrival_planned: None,
+        });
+        let found = |name: &str| find_stopover(&stopovers, name).map(|s| s.name.as_str());
+
+        assert_eq!(found("Linz Hbf"), Some("Linz Hbf"));
+        assert_eq!(found("wien hbf"), Some("Wien Hbf (U)"));
+        assert_eq!(found("Frankfurt (Main) Hbf"), Some("Frankfurt (Main) Hbf"));
+        assert_eq!(found("Frankfurt (Main)"), Some("Frankfurt (Main) Hbf"));
+        assert_eq!(found("Wien Meidling Bahnhof"), Some("Wien Meidling"));
+        // could be either
+        assert_eq!(found("Wien"), None);
+        assert_eq!(found("Linz"), None);
+        assert_eq!(found("Graz Hbf"), None);
+    }
+
+    #[tokio::test]
+    async fn check_in_against_mock_api() {
+        let check_ins = Arc::new(Mutex::new(vec![]));
+        let queries = Arc::new(Mutex::new(vec![]));
+        let app = Router::new()
+            .route(
+                "/api/v1/trains/station/autocomplete/:query",
+                get({
+                    let queries = queries.clone();
+                    move |Path(query): Path<String>| async move {
+                        queries.lock().push(query);
+                        Json(json!({"data": [{"id": 4711, "name": "Salzburg Hbf", "ibnr": 8100002}]}))
+                    }
+                }),
+            )
+            .route(
+                "/api/v1/station/:id/departures",
+                get(|Path(id): Path<u64>| async move {
+                    assert_eq!(id, 4711);
+                    Json(json!({"data": [
+                        {"tripId": "1|111", "plannedWhen": "2024-03-01T13:02:00+01:00", "line": {"name": "S 3", "fahrtNr": "5012"}},
+                        {"tripId": "1|222", "plannedWhen": "2024-03-01T13:08:00+01:00", "line": {"name": "RJ 61", "fahrtNr": "61"}},
+                    ]}))
+                }),
+            )
+            .route(
+                "/api/v1/trains/trip",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    assert_eq!(query["hafasTripId"], "1|222");
+                    assert_eq!(query["start"], "4711");
+                    Json(json!({"data": {"stopovers": [
+                        {"id": 4711, "name": "Salzburg Hbf", "departurePlanned": "2024-03-01T13:08:00+01:00"},
+                        {"id": 4712, "name": "Linz Hbf", "arrivalPlanned": "2024-03-01T14:05:00+01:00"},
+                        {"id": 4713, "name": "Wien Hbf (U)", "arrivalPlanned": "2024-03-01T15:30:00+01:00"},
+                    ]}}))
+                }),
+            )
+            .route(
+                "/api/v1/trains/checkin",
+                post({
+                    let check_ins = check_ins.clone();
+                    move |headers: HeaderMap, Json(body): Json<Value>| async move {
+                        check_ins
+                            .lock()
+                            .push((headers["authorization"].to_str().unwrap().to_string(), body));
+                        Json(json!({"data": {"status": {"id": 99}, "points": {}}}))
+                    }
+                }),
+            );
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base_url = format!("http://{}/api/v1", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let traewelling = Traewelling {
+            client: reqwest::Client::new(),
+            config: TraewellingConfig { base_url },
+            token: Some("secret".to_string()),
+        };
+        let status = rj61();
+        let id = traewelling
+            .check_in(&status, &status.stops[0], &status.stops[2])
+            .await
+            .unwrap();
+        assert_eq!(id, 99);
+
+        let (authorization, body) = check_ins.lock().remove(0);
+        assert_eq!(authorization, "Bearer secret");
+        assert_eq!(
+            body,
+            json!({
+                "tripId": "1|222",
+                "lineName": "RJ 61",
+                "start": 4711,
+                "destination": 4713,
+                "departure": "2024-03-01T13:08:00+01:00",
+                "arrival": "2024-03-01T15:30:00+01:00",
+            })
+        );
+
+        let error = traewelling
+            .check_in(&status, &status.stops[0], &stop("Graz Hbf", None, None))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "RJ 61 doesn't stop at Graz Hbf");
+
+        // names that aren't safe in a URL path as they are
+        let odd = stop("Frankfurt (Main) Hbf/Süd?#%", None, Some("13:08"));
+        traewelling
+            .check_in(&status, &odd, &status.stops[2])
+            .await
+            .unwrap();
+        assert_eq!(
+            *queries.lock(),
+            [
+                "Salzburg Hbf",
+                "Salzburg Hbf",
+                "Frankfurt (Main) Hbf/Süd?#%"
+            ]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Faster than any train we'll be on (km/h).
+pub const MAX_PLAUSIBLE_KMH: f64 = 400.0;
+
+/// Up to this late counts as on time, as in ÖBB's punctuality figures.
+pub const PUNCTUAL_WITHIN_MINUTES: i64 = 5;
+
+/// What we know about the train right now, independent of which portal it
+/// came from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TripStatus {
+    /// In km/h, if the portal reported something that parses or we could
+    /// work it out from `position`.
+    pub speed_kmh: Option<f64>,
+    /// What the portal said, before `speed::SpeedFilter` checked and smoothed
+    /// it and GPS filled in for it.
+    pub raw_speed_kmh: Option<f64>,
+    pub speed_source: SpeedSource,
+    /// The portal's speed is way off from what GPS says.
+    pub speed_implausible: bool,
+    pub position: Option<Position>,
+    pub train_type: Option<String>,
+    pub trip_number: Option<String>,
+    pub destination: Option<String>,
+    pub next_stop: Option<Stop>,
+    /// Our own guess at when we'll be at `next_stop`, see `eta::EtaEstimator`.
+    pub predicted_arrival: Option<DateTime<Utc>>,
+    pub stops: Vec<Stop>,
+}
+
+/// Where the train is, in WGS 84 degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedSource {
+    #[default]
+    Portal,
+    /// From consecutive positions, see `gps::GpsSpeed`.
+    Gps,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+    pub name: String,
+    pub arrival: Times,
+    pub departure: Times,
+    /// Where the station is, if the portal says.
+    pub position: Option<Position>,
+    /// How far along the line the station is, if the portal says.
+    pub km: Option<f64>,
+    /// Trains leaving from here, if the portal lists them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub connections: Vec<Connection>,
+}
+
+/// A train to change to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Connection {
+    /// E.g. `"REX 5"` or `"S1"`.
+    pub line: String,
+    pub destination: Option<String>,
+    pub departure: Times,
+    pub platform: Option<String>,
+}
+
+/// Times of day as the portal shows them, e.g. `"14:05"`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Times {
+    pub scheduled: Option<String>,
+    pub forecast: Option<String>,
+}
+
+impl TripStatus {
+    /// E.g. `"RJ 61"`.
+    pub fn train(&self) -> Option<String> {
+        train_name(self.train_type.as_deref(), self.trip_number.as_deref())
+    }
+
+    /// Index of the stop we're coming from, i.e. the one before the next one.
+    /// `None` before the train has left its first stop or when the portal has
+    /// no stop list.
+    pub fn origin(&self) -> Option<usize> {
+        let next = self.next_stop.as_ref()?;
+        let next = self.stops.iter().position(|stop| stop.name == next.name)?;
+        next.checked_sub(1)
+    }
+
+    /// How late we'll be at the next stop, in minutes.
+    pub fn delay_minutes(&self) -> Option<i64> {
+        self.next_stop.as_ref()?.arrival.delay_minutes()
+    }
+}
+
+impl Position {
+    /// Great-circle distance, which is close enough at the scale of a train
+    /// ride.
+    pub fn distance_km(&self, other: &Position) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+impl Times {
+    /// The forecast if there is one, otherwise the schedule.
+    pub fn best(&self) -> Option<&str> {
+        self.forecast.as_deref().or(self.scheduled.as_deref())
+    }
+
+    pub fn delay_minutes(&self) -> Option<i64> {
+        minutes_between(self.scheduled.as_deref()?, self.forecast.as_deref()?)
+    }
+}
+
+/// From one time of day to another, taking the shorter way around midnight:
+/// 23:58 to 00:03 is 5 minutes, not 23h55 back.
+pub fn minutes_between(from: &str, to: &str) -> Option<i64> {
+    let minutes = (parse_time(to)? - parse_time(from)?).num_minutes();
+    Some(match minutes {
+        m if m < -12 * 60 => m + 24 * 60,
+        m if m > 12 * 60 => m - 24 * 60,
+        m => m,
+    })
+}
+
+/// E.g. `"RJ 61"` from `"RJ"` and `"61"`.
+pub fn train_name(train_type: Option<&str>, trip_number: Option<&str>) -> Option<String> {
+    match (train_type, trip_number) {
+        (Some(train_type), Some(trip_number)) => Some(format!("{train_type} {trip_number}")),
+        (Some(only), None) | (None, Some(only)) => Some(only.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// `time` (as the portal shows it) today, or yesterday or tomorrow if that's
+/// closer to `now`.
+pub fn local_time_near(time: NaiveTime, now: DateTime<Local>) -> DateTime<Local> {
+    let today = now.date_naive().and_time(time);
+    let candidate = Local.from_local_datetime(&today).earliest().unwrap_or(now);
+    match candidate - now {
+        d if d > Duration::hours(12) => candidate - Duration::days(1),
+        d if d < Duration::hours(-12) => candidate + Duration::days(1),
+        _ => candidate,
+    }
+}
+
+pub fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origin_is_the_stop_before_the_next() {
+        let stop = |name: &str| Stop {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut status = TripStatus {
+            next_stop: Some(stop("Linz Hbf")),
+            stops: vec![stop("Salzburg Hbf"), stop("Linz Hbf"), stop("Wien Hbf")],
+            ..Default::default()
+        };
+        assert_eq!(status.origin(), Some(0));
+        status.next_stop = Some(stop("Salzburg Hbf"));
+        assert_eq!(status.origin(), None);
+        status.next_stop = None;
+        assert_eq!(status.origin(), None);
+    }
+
+    #[test]
+    fn distance() {
+        let salzburg = Position {
+            latitude: 47.8128,
+            longitude: 13.0456,
+        };
+        let wien = Position {
+            latitude: 48.1852,
+            longitude: 16.3766,
+        };
+        assert_eq!(salzburg.distance_km(&salzburg), 0.0);
+        assert!((salzburg.distance_km(&wien) - 251.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn times_around_midnight() {
+        let now = Local.with_ymd_and_hms(2024, 3, 1, 23, 50, 0).unwrap();
+        let time = |s| parse_time(s).unwrap();
+        assert_eq!(
+            local_time_near(time("00:10"), now),
+            Local.with_ymd_and_hms(2024, 3, 2, 0, 10, 0).unwrap()
+        );
+        assert_eq!(
+            local_time_near(time("23:30"), now),
+            Local.with_ymd_and_hms(2024, 3, 1, 23, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn delay_across_midnight() {
+        let times = |scheduled: &str, forecast: &str| Times {
+            scheduled: Some(scheduled.to_string()),
+            forecast: Some(forecast.to_string()),
+        };
+        assert_eq!(times("23:58", "00:03").delay_minutes(), Some(5));
+        assert_eq!(times("00:01", "23:59").delay_minutes(), Some(-2));
+        assert_eq!(times("12:00", "--:--").delay_minutes(), None);
+    }
+}
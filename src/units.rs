@@ -0,0 +1,59 @@
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum SpeedUnit {
+    #[default]
+    #[serde(rename = "km/h")]
+    Kmh,
+    #[serde(rename = "mph")]
+    Mph,
+    #[serde(rename = "m/s")]
+    Mps,
+}
+
+impl SpeedUnit {
+    /// In the order they are offered in the menu.
+    #[cfg(target_os = "macos")]
+    pub const ALL: [SpeedUnit; 3] = [Self::Kmh, Self::Mph, Self::Mps];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Kmh => "km/h",
+            Self::Mph => "mph",
+            Self::Mps => "m/s",
+        }
+    }
+
+    pub fn convert_kmh(self, kmh: f64) -> f64 {
+        match self {
+            Self::Kmh => kmh,
+            Self::Mph => kmh / 1.609344,
+            Self::Mps => kmh / 3.6,
+        }
+    }
+
+    pub fn format(self, kmh: f64) -> String {
+        format!("{:.0} {}", self.convert_kmh(kmh), self.symbol())
+    }
+
+    /// In miles for mph, kilometres otherwise.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn format_distance(self, km: f64) -> String {
+        match self {
+            Self::Mph => format!("{:.0} mi", km / 1.609344),
+            Self::Kmh | Self::Mps => format!("{km:.0} km"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_converts_kmh() {
+        assert_eq!(SpeedUnit::Kmh.format(187.2), "187 km/h");
+        assert_eq!(SpeedUnit::Mph.format(160.9344), "100 mph");
+        assert_eq!(SpeedUnit::Mps.format(36.0), "10 m/s");
+    }
+}
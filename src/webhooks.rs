@@ -0,0 +1,526 @@
+use crate::{events::TripEvent, state::SharedState, trip::TripStatus};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+const MAX_BACKOFF_MINUTES: i64 = 30;
+/// Undelivered events older than this aren't interesting anymore.
+const MAX_AGE_DAYS: i64 = 7;
+
+/// One `[[webhooks]]` entry of the config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Which triggers to fire on, all of them by default.
+    #[serde(default = "Trigger::all")]
+    pub on: Vec<Trigger>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// A template for the request body, where `{{event}}`, `{{time}}`,
+    /// `{{train}}`, `{{station}}`, `{{destination}}` and `{{delay_minutes}}`
+    /// are replaced with JSON-escaped text (empty if unknown). Without one the
+    /// body is those fields as a JSON object.
+    pub body: Option<String>,
+    /// For the `delay` trigger, which fires when the delay at the next stop
+    /// grows past this.
+    #[serde(default = "default_delay_threshold")]
+    pub delay_threshold_minutes: i64,
+}
+
+fn default_delay_threshold() -> i64 {
+    5
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// We got on a train, i.e. a portal showed up for a trip we weren't on.
+    Boarding,
+    /// The train left a stop.
+    Departure,
+    /// The train stopped at its destination.
+    Arrival,
+    Delay,
+}
+
+impl Trigger {
+    fn all() -> Vec<Self> {
+        vec![Self::Boarding, Self::Departure, Self::Arrival, Self::Delay]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Boarding => "boarding",
+            Self::Departure => "departure",
+            Self::Arrival => "arrival",
+            Self::Delay => "delay",
+        }
+    }
+}
+
+/// A webhook call waiting to go out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Delivery {
+    url: String,
+    headers: BTreeMap<String, String>,
+    body: String,
+    created_at: DateTime<Utc>,
+    attempts: u32,
+    next_attempt_at: DateTime<Utc>,
+}
+
+/// Webhook calls that haven't gone through yet, kept on disk so that what
+/// happened on a train without internet gets delivered later, even if the app
+/// was restarted in between.
+#[derive(Debug)]
+pub struct Outbox {
+    path: PathBuf,
+    pending: Vec<Delivery>,
+}
+
+impl Outbox {
+    /// An outbox that can't be read is moved aside to `.corrupt` rather than
+    /// keeping the app from starting; what was in it is lost.
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let pending = match fs::read_to_string(&path) {
+            Ok(s) => match serde_json::from_str(&s) {
+                Ok(pending) => pending,
+                Err(e) => {
+                    let corrupt = path.with_extension("json.corrupt");
+                    eprintln!(
+                        "webhook: can't read outbox {}, moving it to {}: {e}",
+                        path.display(),
+                        corrupt.display()
+                    );
+                    fs::rename(&path, corrupt)?;
+                    vec![]
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, pending })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn push(&mut self, delivery: Delivery) {
+        self.pending.push(delivery);
+        self.save();
+    }
+
+    /// Sends everything that is due and keeps what failed for later.
+    async fn deliver_due(&mut self, client: &reqwest::Client, now: DateTime<Utc>) {
+        let mut changed = false;
+        let mut index = 0;
+        while index < self.pending.len() {
+            let delivery = &mut self.pending[index];
+            if now - delivery.created_at > Duration::days(MAX_AGE_DAYS) {
+                eprintln!(
+                    "webhook: giving up on {} after {} attempts",
+                    delivery.url, delivery.attempts
+                );
+                self.pending.remove(index);
+                changed = true;
+                continue;
+            }
+            if delivery.next_attempt_at > now {
+                index += 1;
+                continue;
+            }
+
+            let mut request = client
+                .post(&delivery.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(delivery.body.clone());
+            for (name, value) in &delivery.headers {
+                request = request.header(name, value);
+            }
+            let result = request.send().await;
+            changed = true;
+
+            match result.as_ref().map(|r| r.status()) {
+                Ok(status) if status.is_success() => {
+                    self.pending.remove(index);
+                }
+                // retrying won't change the receiver's mind
+                Ok(status) if status.is_client_error() && status != 408 && status != 429 => {
+                    eprintln!("webhook: {} refused the event: {status}", delivery.url);
+                    self.pending.remove(index);
+                }
+                _ => {
+                    delivery.attempts += 1;
+                    let backoff = 2_i64
+                        .saturating_pow(delivery.attempts)
+                        .min(MAX_BACKOFF_MINUTES);
+                    delivery.next_attempt_at = now + Duration::minutes(backoff);
+                    index += 1;
+                }
+            }
+        }
+        if changed {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let write = || -> io::Result<()> {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // a crash halfway through must not lose what was there before
+            let temp = self.path.with_extension("tmp");
+            fs::write(&temp, serde_json::to_vec_pretty(&self.pending)?)?;
+            fs::rename(temp, &self.path)
+        };
+        if let Err(e) = write() {
+            eprintln!("webhook: can't save outbox {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Turns trip events into webhook calls and delivers them, retrying failed
+/// ones every `RETRY_INTERVAL` until `shutdown` is cancelled.
+pub async fn run(
+    webhooks: Vec<WebhookConfig>,
+    mut outbox: Outbox,
+    state: SharedState,
+    mut events: broadcast::Receiver<TripEvent>,
+    shutdown: CancellationToken,
+) {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("build webhook client");
+    let mut detector = TriggerDetector::default();
+    let mut retry = tokio::time::interval(RETRY_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = retry.tick() => {}
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let status = state.read().status.clone();
+                    if let Some((trigger, fields)) = detector.observe(&event, status.as_ref()) {
+                        for webhook in &webhooks {
+                            if let Some(delivery) = webhook.delivery(trigger, &fields, Utc::now()) {
+                                outbox.push(delivery);
+                            }
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+        }
+
+        if outbox.len() > 0 {
+            // whatever is in flight gets sent again next time, better twice than never
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = outbox.deliver_due(&client, Utc::now()) => {}
+            }
+        }
+    }
+}
+
+type Fields = BTreeMap<&'static str, Option<String>>;
+
+impl WebhookConfig {
+    fn delivery(&self, trigger: Trigger, fields: &Fields, now: DateTime<Utc>) -> Option<Delivery> {
+        if !self.on.contains(&trigger) {
+            return None;
+        }
+        if trigger == Trigger::Delay {
+            let crossed = fields["previous_delay_minutes"]
+                .as_deref()
+                .and_then(|m| m.parse::<i64>().ok())
+                .unwrap_or(0)
+                < self.delay_threshold_minutes
+                && fields["delay_minutes"]
+                    .as_deref()
+                    .and_then(|m| m.parse::<i64>().ok())
+                    .is_some_and(|m| m >= self.delay_threshold_minutes);
+            if !crossed {
+                return None;
+            }
+        }
+
+        let body = match &self.body {
+            Some(template) => render(template, fields),
+            None => json!({
+                "event": fields["event"],
+                "time": fields["time"],
+                "train": fields["train"],
+                "station": fields["station"],
+                "destination": fields["destination"],
+                "delay_minutes": fields["delay_minutes"].as_deref().and_then(|m| m.parse::<i64>().ok()),
+            })
+            .to_string(),
+        };
+        Some(Delivery {
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body,
+            created_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+        })
+    }
+}
+
+fn render(template: &str, fields: &Fields) -> String {
+    fields
+        .iter()
+        .fold(template.to_string(), |body, (name, value)| {
+            let value = value.as_deref().unwrap_or_default();
+            // `"…"` minus the quotes, so the template decides where strings go
+            let escaped = serde_json::to_string(value).expect("strings serialize");
+            body.replace(&format!("{{{{{name}}}}}"), &escaped[1..escaped.len() - 1])
+        })
+}
+
+/// Decides which trip events are worth a webhook.
+#[derive(Debug, Default)]
+struct TriggerDetector {
+    /// So that the portal dropping out for a minute isn't a new boarding.
+    boarded: Option<Option<String>>,
+}
+
+impl TriggerDetector {
+    fn observe(
+        &mut self,
+        event: &TripEvent,
+        status: Option<&TripStatus>,
+    ) -> Option<(Trigger, Fields)> {
+        let train = status.and_then(TripStatus::train);
+        let destination = status.and_then(|s| s.destination.clone());
+        let next_stop = status
+            .and_then(|s| s.next_stop.as_ref())
+            .map(|s| s.name.clone());
+
+        let (trigger, time, station, previous_delay) = match event {
+            TripEvent::Connected { time } => {
+                if self.boarded.as_ref() == Some(&train) {
+                    return None;
+                }
+                self.boarded = Some(train.clone());
+                (Trigger::Boarding, time, next_stop, None)
+            }
+            TripEvent::Departure { time, station } => {
+                (Trigger::Departure, time, station.clone(), None)
+            }
+            TripEvent::Arrival { time, station }
+                if destination.is_some() && *station == destination =>
+            {
+                (Trigger::Arrival, time, station.clone(), None)
+            }
+            TripEvent::Delay {
+                time,
+                station,
+                previous_minutes,
+                ..
+            } => (
+                Trigger::Delay,
+                time,
+                station.clone(),
+                Some(*previous_minutes),
+            ),
+            _ => return None,
+        };
+
+        let delay = match event {
+            TripEvent::Delay { delay_minutes, .. } => *delay_minutes,
+            _ => status.and_then(TripStatus::delay_minutes),
+        };
+        let fields = Fields::from([
+            ("event", Some(trigger.name().to_string())),
+            ("time", Some(time.to_rfc3339())),
+            ("train", train),
+            ("station", station),
+            ("destination", destination),
+            ("delay_minutes", delay.map(|m| m.to_string())),
+            (
+                "previous_delay_minutes",
+                previous_delay.flatten().map(|m| m.to_string()),
+            ),
+        ]);
+        Some((trigger, fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, http::StatusCode, routing::post, Router};
+    use parking_lot::Mutex;
+    use std::{net::Ipv4Addr, sync::Arc};
+    use tokio::net::TcpListener;
+
+    fn rj61() -> TripStatus {
+        TripStatus {
+            train_type: Some("RJ".to_string()),
+            trip_number: Some("61".to_string()),
+            destination: Some("Wien Hbf".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn triggers() {
+        let mut detector = TriggerDetector::default();
+        let time = Utc::now();
+        let status = rj61();
+
+        let (trigger, fields) = detector
+            .observe(&TripEvent::Connected { time }, Some(&status))
+            .unwrap();
+        assert_eq!(trigger, Trigger::Boarding);
+        assert_eq!(fields["train"].as_deref(), Some("RJ 61"));
+        // the portal coming back on the same train
+        assert!(detector
+            .observe(&TripEvent::Connected { time }, Some(&status))
+            .is_none());
+
+        let arrival = |station: &str| TripEvent::Arrival {
+            time,
+            station: Some(station.to_string()),
+        };
+        assert!(detector
+            .observe(&arrival("Linz Hbf"), Some(&status))
+            .is_none());
+        assert_eq!(
+            detector
+                .observe(&arrival("Wien Hbf"), Some(&status))
+                .unwrap()
+                .0,
+            Trigger::Arrival
+        );
+    }
+
+    #[test]
+    fn delay_threshold_and_templates() {
+        let webhook = WebhookConfig {
+            url: "http://localhost/hook".to_string(),
+            on: vec![Trigger::Delay],
+            headers: BTreeMap::new(),
+            body: Some(
+                r#"{"text": "{{train}} is {{delay_minutes}}' late at {{station}}"}"#.to_string(),
+            ),
+            delay_threshold_minutes: 5,
+        };
+        let mut detector = TriggerDetector::default();
+        let delay = |previous: i64, now: i64| TripEvent::Delay {
+            time: Utc::now(),
+            station: Some("St. Pölten \"Hbf\"".to_string()),
+            previous_minutes: Some(previous),
+            delay_minutes: Some(now),
+        };
+        let mut deliveries = |event| {
+            let (trigger, fields) = detector.observe(&event, Some(&rj61())).unwrap();
+            webhook.delivery(trigger, &fields, Utc::now())
+        };
+
+        assert!(deliveries(delay(2, 4)).is_none());
+        assert!(deliveries(delay(6, 8)).is_none());
+        let delivery = deliveries(delay(4, 6)).unwrap();
+        assert_eq!(
+            delivery.body,
+            r#"{"text": "RJ 61 is 6' late at St. Pölten \"Hbf\""}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn outbox_retries_until_delivered() {
+        // fails once, then accepts
+        let received = Arc::new(Mutex::new(vec![]));
+        let app = Router::new().route(
+            "/hook",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: String| async move {
+                    let mut received = received.lock();
+                    received.push((headers["x-token"].to_str().unwrap().to_string(), body));
+                    if received.len() == 1 {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    } else {
+                        StatusCode::NO_CONTENT
+                    }
+                }
+            }),
+        );
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let path =
+            std::env::temp_dir().join(format!("traveltracker-outbox-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let webhook = WebhookConfig {
+            url,
+            on: Trigger::all(),
+            headers: BTreeMap::from([("x-token".to_string(), "secret".to_string())]),
+            body: None,
+            delay_threshold_minutes: 5,
+        };
+        let (_, fields) = TriggerDetector::default()
+            .observe(&TripEvent::Connected { time: Utc::now() }, Some(&rj61()))
+            .unwrap();
+        let now = Utc::now();
+        let client = reqwest::Client::new();
+
+        let mut outbox = Outbox::open(path.clone()).unwrap();
+        outbox.push(webhook.delivery(Trigger::Boarding, &fields, now).unwrap());
+        outbox.deliver_due(&client, now).await;
+        assert_eq!(received.lock().len(), 1);
+
+        // survives a restart, and waits for the backoff
+        let mut outbox = Outbox::open(path.clone()).unwrap();
+        assert_eq!(outbox.len(), 1);
+        outbox.deliver_due(&client, now).await;
+        assert_eq!(received.lock().len(), 1);
+
+        outbox
+            .deliver_due(&client, now + Duration::minutes(5))
+            .await;
+        assert_eq!(outbox.len(), 0);
+        assert_eq!(Outbox::open(path.clone()).unwrap().len(), 0);
+
+        let received = received.lock();
+        assert_eq!(received[1].0, "secret");
+        let body: serde_json::Value = serde_json::from_str(&received[1].1).unwrap();
+        assert_eq!(body["event"], "boarding");
+        assert_eq!(body["train"], "RJ 61");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_outbox_is_moved_aside() {
+        let path = std::env::temp_dir().join(format!(
+            "traveltracker-corrupt-outbox-{}.json",
+            std::process::id()
+        ));
+        let corrupt = path.with_extension("json.corrupt");
+        fs::write(&path, "[{").unwrap();
+
+        let outbox = Outbox::open(path.clone()).unwrap();
+        assert_eq!(outbox.len(), 0);
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&corrupt).unwrap(), "[{");
+        fs::remove_file(corrupt).unwrap();
+    }
+}